@@ -1,5 +1,6 @@
 #![warn(clippy::all)]
 use auto_desk::client;
+use auto_desk::config;
 use auto_desk::server;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use fern::colors::{Color, ColoredLevelConfig};
@@ -8,27 +9,54 @@ use log::error;
 #[tokio::main(flavor = "multi_thread", worker_threads = 10)]
 async fn main() {
     let args = get_args();
-    logger_init();
+    // keep the output clean for scripts, only errors get logged.
+    logger_init(args.contains_id("quiet") || args.contains_id("json"));
 
-    match &args.subcommand() {
-        Some(("start", _)) => server::server_start().await,
-        Some(("stop", _)) => client::stop_server().await,
+    let ec = match &args.subcommand() {
+        Some(("start", sub)) => server::server_start(sub.get_one::<String>("wm").cloned()).await,
+        Some(("stop", _)) => {
+            client::stop_server().await;
+            0
+        }
+        Some(("config", sub)) => client::handle_config(sub, client::Output::from_args(&args)),
+        Some(("completions", sub)) => {
+            let shell = sub.get_one::<String>("shell").unwrap();
+            client::print_completions(&mut build_cli(), shell)
+        }
         Some((_, _)) => client::handle_args(args),
         None => {
             error!("no command specified.");
-            std::process::exit(1);
+            1
         }
-    }
+    };
 
-    std::process::exit(0);
+    std::process::exit(ec.into());
 }
 
 fn get_args() -> ArgMatches {
+    build_cli().get_matches()
+}
+
+fn build_cli() -> App<'static> {
     App::new("auto-desk")
         .version("0.5.0")
         .author("Calacuda. <https://github.com/calacuda>")
         .about("used to control a linux desktop running BSPWM.")
         .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("print the server's response as json: {code, meaning, message}")
+                .global(true)
+                .conflicts_with("quiet"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("print nothing, only exit with the server's error code")
+                .global(true),
+        )
         .subcommand(
             SubCommand::with_name("layout")
                 .about("configure the system with a layout.yaml file")
@@ -76,17 +104,96 @@ fn get_args() -> ArgMatches {
         .subcommand(
             SubCommand::with_name("start")
                 .about("starts the server")
-                .help("starts the server"),
+                .help("starts the server")
+                .arg(
+                    Arg::new("wm")
+                        .long("wm")
+                        .value_name("WM")
+                        .help("the window manager to control, overrides `wm` in the config. (default: auto)")
+                        .possible_values(config::WM_SETTINGS)
+                        .takes_value(true),
+                ),
         )
         .subcommand(
             SubCommand::with_name("stop")
                 .about("stops the server and cleans up the file system.")
                 .help("stops the server and cleans up the file system."),
         )
-        .get_matches()
+        .subcommand(
+            SubCommand::with_name("raw")
+                .about("sends a command to the server as is. (eg. auto-desk raw \"vol-up 5\")")
+                .arg(
+                    Arg::new("command")
+                        .value_name("COMMAND")
+                        .help("the command and its arguments, as one string")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("events")
+                .about("prints the server's events as lines of json until it stops. (eg. for status bars)")
+                .arg(
+                    Arg::new("filter")
+                        .short('f')
+                        .long("filter")
+                        .value_name("EVENTS")
+                        .help("comma separated events to print, \"volume\" also matches \"volume-changed\". (default: all)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("checks the config file or prints the default one")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("check")
+                        .about("loads the config file the way the server does and reports what's wrong with it")
+                        .arg(
+                            Arg::new("file")
+                                .value_name("FILE")
+                                .help("the config file to check. (default: ~/.config/auto-desk/config.toml)")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("default")
+                        .about("prints the default config, with every setting explained"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("prints a shell completion script. (eg. auto-desk completions bash > /usr/share/bash-completion/completions/auto-desk)")
+                .arg(
+                    Arg::new("shell")
+                        .value_name("SHELL")
+                        .help("the shell to generate completions for")
+                        .possible_values(client::COMPLETION_SHELLS)
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(client::COMPLETE_CMD)
+                .about("prints completion candidates, used by the completion scripts")
+                .hide(true)
+                .arg(
+                    Arg::new("kind")
+                        .possible_values(["layouts", "desktops"])
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommands(client::server_subcommands())
 }
 
-fn logger_init() {
+fn logger_init(errors_only: bool) {
+    let level = if errors_only {
+        log::LevelFilter::Error
+    } else {
+        log::LevelFilter::Trace
+    };
+
     let colors = ColoredLevelConfig::new()
         .debug(Color::Blue)
         .info(Color::Green)
@@ -103,6 +210,7 @@ fn logger_init() {
                 message
             ))
         })
+        .level(level)
         .filter(|metadata| metadata.target().starts_with("auto_desk"))
         .chain(std::io::stderr())
         // .chain(fern::log_file("output.log")?)
@@ -117,6 +225,7 @@ fn logger_init() {
                 message
             ))
         })
+        .level(level)
         .filter(|metadata| metadata.target().starts_with("auto_desk"))
         .chain(std::io::stderr())
         // .chain(fern::log_file("output.log")?)
@@ -198,11 +198,9 @@ fn write_file(file: &str, mesg: &str, file_desc: &str) -> Result<(), String> {
     }
 }
 
-/// sends data to auto-desk via a named pipe at, get_pipe_f(), (usually, "/run/user/<UID>/auto-desk.pipe")
-fn send_data(mesg: &str) -> Result<(), String> {
-    let pipe_f = get_pipe_f();
-
-    let mut stream = match UnixStream::connect(&pipe_f) {
+/// sends data to auto-desk via the socket at, pipe_f, (usually, "/run/user/<UID>/auto-desk/auto-desk.ports")
+fn send_data(pipe_f: &str, mesg: &str) -> Result<(), String> {
+    let mut stream = match UnixStream::connect(pipe_f) {
         Ok(stream) => stream,
         Err(_) => return Ok(()),
     };
@@ -253,6 +251,9 @@ fn prepare_tracer() -> Result<(), String> {
 }
 
 fn main() {
+    // port-sentinel runs as root so it can't find the users runtime dir on its own.
+    let pipe_f = std::env::args().nth(1).unwrap_or_else(get_pipe_f);
+
     if let Err(mesg) = prepare_tracer() {
         eprintln!("[ERROR] {mesg}");
         eprintln!("[FATAL ERROR] could not prepare tracer. not running auto-desk port-sentinel.");
@@ -262,7 +263,7 @@ fn main() {
     loop {
         for mesg in trace_loop() {
             // eprintln!("[LOG] sending message: {i} => {:#?}", mesg);
-            if let Err(err_message) = send_data(&mesg) {
+            if let Err(err_message) = send_data(&pipe_f, &mesg) {
                 eprintln!("[ERROR] {err_message}");
             }
         }
@@ -1,18 +1,145 @@
 use crate::common::open_program;
-use crate::config::OptGenRes;
+use crate::config::{GenericRes, OptGenRes};
 use crate::wm_lib;
-use crate::wm_lib::{DesktopLayout, Program};
+use crate::wm_lib::Program;
 use freedesktop_entry_parser::parse_entry;
-use log::{error, info};
+use log::{error, info, warn};
 use procfs::process;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::env::set_current_dir;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::os::unix::net::UnixStream;
 use std::path::Path;
 use std::process::{Command, Stdio};
-use std::{thread, time};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+use crate::fake_socket::FakeSocket;
 use xdotool::window::get_window_pid;
 
+/// how long to wait for a launched program's window when its `delay` doesn't say.
+const WINDOW_TIMEOUT: Duration = Duration::from_millis(5000);
+/// held while rules are added, listed, or removed. `rule -r ^N` goes by position, so a parallel
+/// (`asyncro`) launch changing the rules in between would get the wrong ones removed.
+static RULES: Mutex<()> = Mutex::new(());
+/// the message that turns a connection into a stream of the events the server uses.
+pub const SUBSCRIBE: &str = "subscribe node_add node_remove desktop_focus";
+
+/// an event read from a `subscribe` connection. desktops and nodes are bspwm ids
+/// (eg. "0x00400002").
+#[derive(Debug, PartialEq)]
+pub enum BspwmEvent {
+    NodeAdd { desktop: String, node: String },
+    NodeRemove { desktop: String, node: String },
+    DesktopFocus { desktop: String },
+}
+
+/// parses a line of a `subscribe` connection. (eg. "node_add <monitor> <desktop> <ip> <node>")
+pub fn parse_event(line: &str) -> Option<BspwmEvent> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    match fields.as_slice() {
+        ["node_add", _monitor, desktop, _ip, node] => Some(BspwmEvent::NodeAdd {
+            desktop: desktop.to_string(),
+            node: node.to_string(),
+        }),
+        ["node_remove", _monitor, desktop, node] => Some(BspwmEvent::NodeRemove {
+            desktop: desktop.to_string(),
+            node: node.to_string(),
+        }),
+        ["desktop_focus", _monitor, desktop] => Some(BspwmEvent::DesktopFocus {
+            desktop: desktop.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// a connection subscribed to bspwm's events. it's made before a program is launched so its
+/// window can't be missed.
+struct Subscription {
+    lines: BufReader<UnixStream>,
+}
+
+impl Subscription {
+    fn new(spath: &str) -> std::io::Result<Subscription> {
+        let mut stream = UnixStream::connect(spath)?;
+        stream.write_all(&make_api(SUBSCRIBE))?;
+
+        Ok(Subscription {
+            lines: BufReader::new(stream),
+        })
+    }
+
+    /// waits for a new node that `wanted` accepts and returns it and its desktop. returns None
+    /// if none shows up before `deadline`.
+    fn wait_for_node(
+        &mut self,
+        deadline: Instant,
+        wanted: impl Fn(&str) -> bool,
+    ) -> Option<(String, String)> {
+        let mut line = String::new();
+
+        loop {
+            let left = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|left| !left.is_zero())?;
+            line.clear();
+
+            let read = self
+                .lines
+                .get_ref()
+                .set_read_timeout(Some(left))
+                .and_then(|_| self.lines.read_line(&mut line));
+            match read {
+                Ok(0) => {
+                    warn!("bspwm closed the event subscription");
+                    return None;
+                }
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return None
+                }
+                Err(e) => {
+                    warn!("could not read bspwm's events. got error: \"{e}\"");
+                    return None;
+                }
+            }
+
+            if let Some(BspwmEvent::NodeAdd { desktop, node }) = parse_event(&line) {
+                if wanted(&node) {
+                    return Some((node, desktop));
+                }
+            }
+        }
+    }
+}
+
+/// returns the name of the desktop with the id `desktop`, or the id if bspwm doesn't know it.
+pub fn desktop_name(spath: &str, desktop: &str) -> String {
+    let name = query(spath, &format!("query -D -d {desktop} --names"));
+
+    match name.trim() {
+        "" => desktop.to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// returns the (lowercase) class and instance names of the window `node`.
+pub fn node_classes(spath: &str, node: &str) -> Vec<String> {
+    let tree = query(spath, &format!("query -T -n {node}"));
+
+    match serde_json::from_str::<Value>(&tree) {
+        Ok(tree) => ["className", "instanceName"]
+            .iter()
+            .filter_map(|key| tree["client"][key].as_str())
+            .map(str::to_lowercase)
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
 fn remove_present(progs: &Vec<Program>, execs: &mut Vec<String>) -> Vec<Program> {
     let mut programs = Vec::new();
     for program in progs {
@@ -83,146 +210,199 @@ fn load_layout(spath: &str, args: &str) -> u8 {
     // stop the window manager from following to the newest window. not actually necessary.
     send(spath, "config ignore_ewmh_focus true");
 
-    let error_code = load_from_yaml(layout_yaml.desktops, spath);
+    let tmp_spath = spath.to_string();
+    let error_code = wm_lib::launch_layouts(layout_yaml.desktops, move |layout| {
+        set_up_desktop(&layout.desktop, &layout.programs, &tmp_spath)
+    });
 
     send(spath, "config ignore_ewmh_focus false");
 
-    // TODO: set workspaces to desktops
+    if error_code > 0 {
+        return error_code;
+    }
 
-    error_code
+    match layout_yaml.workspaces {
+        Some(workspaces) => set_workspaces(spath, &workspaces),
+        None => 0,
+    }
 }
 
-fn load_from_yaml(layouts: Vec<DesktopLayout>, spath: &str) -> u8 {
-    let mut async_layouts = Vec::new();
-    let mut sync_layouts = Vec::new();
-
-    for layout in layouts {
-        match layout.asyncro {
-            Some(b) if b => async_layouts.push(layout),
-            _ => sync_layouts.push(layout),
+/// moves desktops to the monitors (workspaces) described by the layout's `workspaces` map.
+/// both monitors and desktops are counted from zero.
+fn set_workspaces(spath: &str, workspaces: &HashMap<i32, i32>) -> u8 {
+    for (workspace, desktop) in workspaces {
+        let ec = send(
+            spath,
+            &format!("desktop ^{} -m ^{}", desktop + 1, workspace + 1),
+        );
+        if ec > 0 {
+            error!("could not move desktop {desktop} to monitor {workspace}");
+            return ec;
         }
     }
 
-    let mut launchers = Vec::new();
-
-    let tmp_spath = spath.to_string();
-    launchers.push(thread::spawn(move || {
-        init_layouts(&tmp_spath, &sync_layouts)
-    }));
+    0
+}
 
-    for layout in async_layouts {
-        let tmp_spath = spath.to_owned().clone();
-        launchers.push(thread::spawn(move || init_layout(&tmp_spath, &layout)));
+fn apply_workspaces(spath: &str, args: &str) -> u8 {
+    match wm_lib::get_workspaces(args) {
+        Ok(workspaces) => set_workspaces(spath, &workspaces),
+        Err(n) => n,
     }
+}
 
-    for launcher in launchers {
-        let err_codes = match launcher.join() {
-            Ok(ecs) => ecs,
-            Err(e) => {
-                error!("got unknown error: {e:?}");
-                vec![2]
-            }
-        };
-        for ec in err_codes {
-            if ec > 0 {
-                return ec;
-            }
-        }
-    }
+fn set_up_desktop(desktop_name: &str, programs: &Vec<Program>, spath: &str) -> Vec<u8> {
+    let progs = get_progs(desktop_name, programs, spath);
 
-    0
+    wm_lib::launch_programs(&progs, desktop_name, |program| {
+        let exec = get_exec(program).to_lowercase();
+        run_exec(&exec, desktop_name, program, spath)
+    })
 }
 
-fn init_layouts(spath: &str, layouts: &Vec<DesktopLayout>) -> Vec<u8> {
-    let mut res_codes = Vec::new();
+/// returns the rule causes (`class:instance`) a window of `exec` may match, none if `exec` is
+/// empty.
+fn rule_causes(exec: &str) -> Vec<String> {
+    let mut chars = exec.chars();
+    let Some(capitalized) = chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+    else {
+        return Vec::new();
+    };
 
-    for layout in layouts {
-        res_codes.append(&mut init_layout(spath, layout));
-    }
+    vec![
+        format!("{capitalized}:{exec}"),
+        format!("{capitalized}:{capitalized}"),
+        format!("{exec}:{exec}"),
+        format!("*:{exec}"),
+        capitalized,
+        exec.to_string(),
+    ]
+}
 
-    res_codes
+/// returns the (one based) indexes of the one-shot rules in `listing` (the output of `rule -l`)
+/// that were added for `causes` and `desktop_name`, last first so they can be removed in order.
+fn stale_rules(listing: &str, causes: &[String], desktop_name: &str) -> Vec<usize> {
+    let causes: Vec<String> = causes
+        .iter()
+        .map(|cause| {
+            let mut parts: Vec<&str> = cause.split(':').collect();
+            parts.resize(3, "*");
+            parts.join(":")
+        })
+        .collect();
+    let effect = format!("desktop={desktop_name}");
+
+    let mut indexes: Vec<usize> = listing
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| match line.split_once(" -> ") {
+            Some((cause, effects)) => {
+                causes.iter().any(|c| c == cause) && effects.split_whitespace().any(|e| e == effect)
+            }
+            None => false,
+        })
+        .map(|(i, _)| i + 1)
+        .collect();
+    indexes.reverse();
+    indexes
 }
 
-fn init_layout(spath: &str, layout: &DesktopLayout) -> Vec<u8> {
-    let desktop_num = layout.desktop.to_string();
-    let programs = layout.programs.clone();
-    let tmp_spath = spath.to_string();
-    set_up_desktop(&desktop_num, &programs, &tmp_spath)
+fn lock_rules() -> MutexGuard<'static, ()> {
+    RULES.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
-fn set_up_desktop(desktop_name: &str, programs: &Vec<Program>, spath: &str) -> Vec<u8> {
-    let progs = get_progs(desktop_name, programs, spath);
-    let mut ecs = Vec::new();
+/// removes the one-shot rules for `causes` that weren't used. only these are removed, so rules
+/// from the user's bspwmrc with the same causes are kept.
+fn remove_rules(spath: &str, causes: &[String], desktop_name: &str) {
+    let _rules = lock_rules();
+    let listing = query(spath, "rule -l");
 
-    for program in progs {
-        let exec = get_exec(&program).to_lowercase();
-        let ec = run_exec(&exec, desktop_name, &program, spath);
-        ecs.push(ec);
-        if ec > 0 {
-            error!(
-                "count not launch {} on desktop {}.",
-                program.name, desktop_name
-            );
+    for index in stale_rules(&listing, causes, desktop_name) {
+        if send(spath, &format!("rule -r ^{index}")) > 0 {
+            warn!("could not remove a stale rule for desktop {desktop_name}");
         }
     }
+}
 
-    ecs
-}
-
-fn run_exec(exec: &String, desktop_name: &str, program: &Program, spath: &str) -> u8 {
-    let rules = [
-        format!(
-            "{}:{} desktop={}",
-            exec[0..1].to_uppercase() + &exec[1..],
-            exec,
-            desktop_name
-        ),
-        format!(
-            "{}:{} desktop={}",
-            exec[0..1].to_uppercase() + &exec[1..],
-            exec[0..1].to_uppercase() + &exec[1..],
-            desktop_name
-        ),
-        format!("{}:{} desktop={}", exec, exec, desktop_name),
-        format!("*:{} desktop={}", exec, desktop_name),
-        format!(
-            "{} desktop={}",
-            exec[0..1].to_uppercase() + &exec[1..],
-            desktop_name
-        ),
-        format!("{} desktop={}", exec, desktop_name),
-    ];
-
-    for rule in &rules {
-        if send(spath, &format!("rule -a {} follow=off -o", &rule)) > 0 {
-            return 3;
+/// launches `program` with one-shot rules that send its window to `desktop_name` and waits for
+/// the window to show up. a window that lands elsewhere is moved, and the rules that weren't
+/// used are removed either way.
+fn run_exec(exec: &str, desktop_name: &str, program: &Program, spath: &str) -> u8 {
+    let causes = rule_causes(exec);
+
+    let mut events = match Subscription::new(spath) {
+        Ok(events) => events,
+        Err(e) => {
+            error!("could not subscribe to bspwm's events. got error: \"{e}\"");
+            return 5;
+        }
+    };
+
+    let mut error_code = 0;
+    let rules = lock_rules();
+    for cause in &causes {
+        if send(
+            spath,
+            &format!("rule -a {cause} desktop={desktop_name} follow=off -o"),
+        ) > 0
+        {
+            error_code = 3;
+            break;
         }
     }
+    drop(rules);
 
-    // thread::sleep(time::Duration::from_millis(100));
+    if error_code == 0 {
+        error_code = open_program(&program.name);
+    }
 
-    let error_code = open_program(&program.name);
+    if error_code == 0 {
+        let timeout = match program.delay {
+            Some(times) => Duration::from_millis(500 * times as u64),
+            None => WINDOW_TIMEOUT,
+        };
+        error_code = place_window(&mut events, spath, exec, program, desktop_name, timeout);
+    }
 
-    // bspwm::open_on_desktop(spath, &format!("{} {}", &program.name, desktop_name));
-    let t = match program.delay {
-        Some(times) => time::Duration::from_millis(500 * times as u64),
-        None => time::Duration::from_millis(500),
-    };
+    remove_rules(spath, &causes, desktop_name);
 
-    if error_code > 0 {
-        return error_code;
-    }
+    error_code
+}
 
-    thread::sleep(t);
+/// waits for the window of `program` and makes sure it ended up on `desktop_name`.
+fn place_window(
+    events: &mut Subscription,
+    spath: &str,
+    exec: &str,
+    program: &Program,
+    desktop_name: &str,
+    timeout: Duration,
+) -> u8 {
+    let mut names = vec![exec.to_lowercase()];
+    names.extend(program.wm_class.as_ref().map(|class| class.to_lowercase()));
+
+    let window = events.wait_for_node(Instant::now() + timeout, |node| {
+        node_classes(spath, node)
+            .iter()
+            .any(|class| names.contains(class))
+    });
+
+    let Some((node, desktop)) = window else {
+        warn!(
+            "no window from {} showed up within {timeout:?}",
+            program.name
+        );
+        return 0;
+    };
 
-    for rule in &rules {
-        // println!("{} | {}", exec, rule);
-        if send(spath, &format!("rule -r {}", &rule)) > 0 {
-            return 3;
-        }
+    if query(spath, &format!("query -D -d {desktop_name}")).trim() == desktop {
+        0
+    } else {
+        info!("a window of {} missed its rule, moving it", program.name);
+        send(spath, &format!("node {node} -d {desktop_name}"))
     }
-
-    0
 }
 
 fn query(spath: &str, message: &str) -> String {
@@ -267,7 +447,13 @@ fn open_on_desktop(spath: &str, raw_args: &str) -> u8 {
         return 3;
     }
 
-    let init_nodes_n = query(spath, "query -N -d").len();
+    let mut events = match Subscription::new(spath) {
+        Ok(events) => events,
+        Err(e) => {
+            error!("could not subscribe to bspwm's events. got error: \"{e}\"");
+            return 5;
+        }
+    };
 
     let cmd = if program.ends_with(".desktop") {
         Command::new("gtk-launch")
@@ -293,11 +479,11 @@ fn open_on_desktop(spath: &str, raw_args: &str) -> u8 {
         }
     };
 
-    let t = time::Duration::from_millis(100);
-
-    while init_nodes_n == query(spath, "query -N -d").len() {
-        // println!("[DEBUG] sleeping...");
-        thread::sleep(t);
+    if events
+        .wait_for_node(Instant::now() + WINDOW_TIMEOUT, |_| true)
+        .is_none()
+    {
+        warn!("no window from {program} showed up within {WINDOW_TIMEOUT:?}");
     }
 
     process
@@ -327,11 +513,22 @@ fn move_to(spath: &str, destination: &str) -> u8 {
     send(spath, &format!("node -d {}", destination))
 }
 
+/// responds with the names of all desktops, one per line.
+fn query_desktops(spath: &str) -> GenericRes {
+    let res = query(spath, "query -D --names");
+
+    if res.is_empty() {
+        (5, None)
+    } else {
+        (0, Some(res.trim().to_string()))
+    }
+}
+
 fn close_focused(spath: &str) -> u8 {
     send(spath, "node -c")
 }
 
-fn make_api(message: &str) -> Vec<u8> {
+pub(crate) fn make_api(message: &str) -> Vec<u8> {
     let null = &format!("{}", 0 as char);
     let mut res = message.replace(' ', null).as_bytes().to_vec();
     res.push(0);
@@ -379,6 +576,120 @@ pub async fn bspwm_switch(cmd: &str, args: &str, spath: &str) -> OptGenRes {
         "open-at" => Some((open_on_desktop(spath, args), None)),
         "focus-on" => Some((focus_on(spath, args), None)),
         "load-layout" => Some((load_layout(spath, args), None)),
+        "apply-workspaces" => Some((apply_workspaces(spath, args), None)),
+        "query-desktops" => Some(query_desktops(spath)),
         _ => None,
     }
 }
+
+#[test]
+fn test_subscription() {
+    assert_eq!(
+        parse_event("node_add 0x00200002 0x00200003 0 0x01400003\n"),
+        Some(BspwmEvent::NodeAdd {
+            desktop: "0x00200003".to_string(),
+            node: "0x01400003".to_string()
+        })
+    );
+    assert_eq!(
+        parse_event("node_focus 0x00200002 0x00200003 0x01400003"),
+        None
+    );
+
+    let listing = "Gimp:*:* => desktop=^8 state=floating\nKitty:kitty:* -> desktop=3 follow=off\n\
+                   *:kitty:* -> desktop=2 follow=off\nkitty:*:* -> desktop=3 follow=off\n";
+    assert_eq!(stale_rules(listing, &rule_causes("kitty"), "3"), [4, 2]);
+    assert!(rule_causes("").is_empty());
+    assert_eq!(rule_causes("éclair")[4], "Éclair");
+}
+
+/// reads a message the way bspwm does, up to its last NUL, with the NUL separators turned back
+/// into spaces.
+#[cfg(test)]
+fn read_message(stream: &mut UnixStream) -> String {
+    let mut message = Vec::new();
+    let mut buf = [0; 1024];
+    while !message.ends_with(&[0]) {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(len) => message.extend_from_slice(&buf[..len]),
+        }
+    }
+
+    String::from_utf8_lossy(message.strip_suffix(&[0]).unwrap_or_default()).replace('\0', " ")
+}
+
+#[test]
+fn test_fake_bspwm() {
+    let fake = FakeSocket::answering("bspwm-send", read_message, |message| match message {
+        "query -D --names" => b"1\n2\nweb\n".to_vec(),
+        "desktop -f mail" => b"\x07desktop -f: Descriptor not found: 'mail'.\n".to_vec(),
+        _ => Vec::new(),
+    });
+    let socket = fake.path.to_str().unwrap();
+
+    assert_eq!(move_to(socket, "^3"), 0);
+    assert_eq!(focus_on(socket, "mail"), 6);
+    assert_eq!(close_focused(socket), 0);
+    assert_eq!(query_desktops(socket), (0, Some("1\n2\nweb".to_string())));
+    assert_eq!(
+        fake.received(),
+        [
+            "node -d ^3",
+            "desktop -f mail",
+            "node -c",
+            "query -D --names"
+        ]
+    );
+
+    fake.remove();
+    assert_eq!(move_to(socket, "^3"), 5);
+    assert_eq!(query_desktops(socket), (5, None));
+}
+
+#[test]
+fn test_fake_bspwm_rules() {
+    let fake = FakeSocket::answering("bspwm-rules", read_message, |message| match message {
+        SUBSCRIBE => b"node_add 0x00200002 0x00200004 0 0x01400003\n".to_vec(),
+        "query -T -n 0x01400003" => {
+            br#"{"client":{"className":"True","instanceName":"true"}}"#.to_vec()
+        }
+        "query -D -d 3" => b"0x00200005\n".to_vec(),
+        "rule -l" => b"Gimp:*:* => desktop=^8\nTrue:true:* -> desktop=3 follow=off\n".to_vec(),
+        _ => Vec::new(),
+    });
+    let program = Program {
+        name: "true".to_string(),
+        state: None,
+        wm_class: None,
+        args: None,
+        delay: Some(4),
+    };
+
+    assert_eq!(
+        run_exec("true", "3", &program, fake.path.to_str().unwrap()),
+        0
+    );
+
+    let received = fake.received();
+    assert_eq!(received[0], SUBSCRIBE);
+    assert_eq!(
+        received[1..7],
+        rule_causes("true")
+            .iter()
+            .map(|cause| format!("rule -a {cause} desktop=3 follow=off -o"))
+            .collect::<Vec<_>>()
+    );
+    // the window opened on another desktop (0x00200004), so it's moved to 3.
+    assert_eq!(
+        received[7..],
+        [
+            "query -T -n 0x01400003",
+            "query -D -d 3",
+            "node 0x01400003 -d 3",
+            "rule -l",
+            "rule -r ^2"
+        ]
+    );
+    fake.remove();
+}
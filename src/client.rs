@@ -1,36 +1,134 @@
 // #![deny(clippy::all)]
+use crate::commands;
 use crate::config;
-use crate::server::{get_running_wm, WindowManager};
-use clap::ArgMatches;
+use crate::msgs::code_meaning;
+use crate::server::{detect_wm, WindowManager, SUBSCRIBE_CMD};
+use crate::wm_lib;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use clap_complete::{generate, Shell};
 use log::{error, info};
+use regex::Regex;
 use std::fs::remove_file;
 use std::io::Read;
 use std::io::Write;
+use std::io::{BufRead, BufReader};
 use std::net::Shutdown;
 use std::os::unix::net::UnixStream;
 use std::path::Path;
-use std::process::exit;
 use std::str;
 
 type ErrorCode = u8;
 
-/// entry point to client.rs  when running a subcommand other then "stop"
-pub fn handle_args(args: ArgMatches) {
+/// how the client reports the servers response.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Output {
+    /// prints the message (if there is one) to stdout.
+    Plain,
+    /// prints `{"code": .., "meaning": .., "message": ..}` to stdout.
+    Json,
+    /// prints nothing, the exit code is the only output.
+    Quiet,
+}
+
+impl Output {
+    pub fn from_args(args: &ArgMatches) -> Output {
+        if args.contains_id("json") {
+            Output::Json
+        } else if args.contains_id("quiet") {
+            Output::Quiet
+        } else {
+            Output::Plain
+        }
+    }
+}
+
+/// makes a sub-command for every server command in the command registry. "subscribe" is left
+/// out because it never ends, it's the "events" sub-command instead.
+pub fn server_subcommands() -> Vec<App<'static>> {
+    commands::enabled_commands()
+        .filter(|spec| spec.name != SUBSCRIBE_CMD)
+        .map(|spec| {
+            let mut subcommand = SubCommand::with_name(spec.name).about(spec.about);
+
+            for arg in spec.args {
+                subcommand = subcommand.arg(
+                    Arg::new(arg.name)
+                        .help(arg.help)
+                        .takes_value(true)
+                        .multiple_values(arg.rest)
+                        .required(arg.required),
+                );
+            }
+
+            subcommand
+        })
+        .collect()
+}
+
+/// entry point to client.rs  when running a subcommand other then "stop". returns the error
+/// code the server responded with.
+pub fn handle_args(args: ArgMatches) -> ErrorCode {
     // let args = get_args();
     let subargs = args.subcommand().unwrap();
+    let output = Output::from_args(&args);
     let configs = match config::get_configs() {
         Ok(configs) => configs,
         Err(e) => {
             error!("{}", e);
-            return;
+            return print_response((8, format!("could not load configs: {e}")), output);
         }
     };
     let server_soc: String = configs.server.listen_socket;
+    let wm_setting = configs.server.wm.unwrap_or_else(|| "auto".to_string());
 
-    match subargs.0 {
-        "launch" => handle_launch(subargs.1.to_owned(), server_soc),
+    let res = match subargs.0 {
+        "launch" => handle_launch(subargs.1.to_owned(), server_soc, &wm_setting),
         "layout" => handle_layout(subargs.1.to_owned(), server_soc),
-        _ => panic!("argument not yet implemented!"),
+        "raw" => handle_raw(subargs.1, &server_soc),
+        "events" => return handle_events(subargs.1, &server_soc, output),
+        "status" if output == Output::Plain => handle_status(&server_soc),
+        COMPLETE_CMD => handle_complete(subargs.1, &server_soc),
+        cmd => match commands::get_command(cmd) {
+            Some(spec) => handle_command(spec, subargs.1, &server_soc),
+            None => {
+                error!("unknown command \"{cmd}\"");
+                (1, format!("unknown command \"{cmd}\""))
+            }
+        },
+    };
+
+    print_response(res, output)
+}
+
+/// entry point to client.rs when running subcommand "config". "check" loads the config file the
+/// way the server does and reports what's wrong with it, "default" prints the default config.
+pub fn handle_config(args: &ArgMatches, output: Output) -> ErrorCode {
+    match args.subcommand() {
+        Some(("check", check_args)) => {
+            let path = check_args
+                .get_one::<String>("file")
+                .map(|path| shellexpand::tilde(path).to_string())
+                .unwrap_or_else(config::config_file);
+
+            let res = if !Path::new(&path).exists() {
+                (
+                    0,
+                    format!("there is no config file at \"{path}\", the defaults will be used"),
+                )
+            } else {
+                match config::load_configs(&path) {
+                    Ok(_) => (0, format!("\"{path}\" is a valid config")),
+                    Err(e) => (4, e),
+                }
+            };
+
+            print_response(res, output)
+        }
+        Some(("default", _)) => {
+            print!("{}", config::DEFAULT_CONFIG);
+            0
+        }
+        _ => 1,
     }
 }
 
@@ -92,7 +190,8 @@ fn clean_fs(server_soc: &str) -> Result<(), Vec<String>> {
     }
 }
 
-fn send_data(data: String, server_soc: &str) -> (ErrorCode, String) {
+/// connects to the server and sends it `data`. returns the stream to read the response from.
+fn send_request(data: String, server_soc: &str) -> Result<UnixStream, (ErrorCode, String)> {
     let mut stream = match UnixStream::connect(server_soc) {
         Ok(stream) => stream,
         Err(_) => {
@@ -100,7 +199,7 @@ fn send_data(data: String, server_soc: &str) -> (ErrorCode, String) {
             info!("Error Hints:");
             info!(" - Do you have the socket configured corectly?");
             info!(" - Is the server running?");
-            exit(1);
+            return Err((8, format!("couldn't connect to socket at \"{server_soc}\"")));
         }
     };
 
@@ -108,7 +207,7 @@ fn send_data(data: String, server_soc: &str) -> (ErrorCode, String) {
         Ok(_) => {}
         Err(e) => {
             error!("sending data to server produced error: {e}");
-            exit(1);
+            return Err((8, format!("sending data to server produced error: {e}")));
         }
     };
 
@@ -120,22 +219,35 @@ fn send_data(data: String, server_soc: &str) -> (ErrorCode, String) {
         }
     };
 
+    Ok(stream)
+}
+
+pub(crate) fn send_data(data: String, server_soc: &str) -> (ErrorCode, String) {
+    let mut stream = match send_request(data, server_soc) {
+        Ok(stream) => stream,
+        Err(res) => return res,
+    };
+
     let mut response_bytes = Vec::new();
     match stream.read_to_end(&mut response_bytes) {
         Ok(_) => {}
         Err(e) => {
             error!("reading server response resulted in error: \"{e}\"");
-            exit(1);
+            return (
+                8,
+                format!("reading server response resulted in error: \"{e}\""),
+            );
         }
     };
 
     let (ec, response) = if !response_bytes.is_empty() {
         let ec = response_bytes[0];
-        let response = &response_bytes[1..];
+        // skip the success/error byte that separates the error code from the message.
+        let response = response_bytes.get(2..).unwrap_or_default();
         (ec, response)
     } else {
         error!("server gave no response check server logs.");
-        return (7, String::new());
+        return (8, String::new());
     };
 
     if ec > 0 {
@@ -150,7 +262,7 @@ fn send_data(data: String, server_soc: &str) -> (ErrorCode, String) {
     (ec, String::from(res_text))
 }
 
-fn handle_layout(args: ArgMatches, server_soc: String) {
+fn handle_layout(args: ArgMatches, server_soc: String) -> (ErrorCode, String) {
     let input_layout_fname: String = args.get_one::<String>("layout").unwrap().clone();
     let layout_path = input_layout_fname; // find_layout(input_layout_fname);
     info!(
@@ -158,17 +270,21 @@ fn handle_layout(args: ArgMatches, server_soc: String) {
         Path::new(&layout_path).to_str().unwrap()
     );
 
-    let (_ec, _response_bytes) = send_data(format!("load-layout {}", layout_path), &server_soc);
+    send_data(format!("load-layout {}", layout_path), &server_soc)
 }
 
-fn handle_launch(args: ArgMatches, server_soc: String) {
+fn handle_launch(args: ArgMatches, server_soc: String, wm_setting: &str) -> (ErrorCode, String) {
     let program = args.get_one::<String>("program").unwrap().clone();
     info!("launching {}...", program);
 
-    let payload = if get_running_wm() == WindowManager::Qtile && args.contains_id("desktop") {
+    let payload = if detect_wm(wm_setting) == WindowManager::Qtile && args.contains_id("desktop") {
         let Some(wm_class) = args.get_one::<String>("wm-class") else {
             error!("the \"--wm-class\"/\"-c\" arguemnt is required when running in Qtile mode");
-            return
+            return (
+                7,
+                "the \"--wm-class\"/\"-c\" arguemnt is required when running in Qtile mode"
+                    .to_string(),
+            );
         };
 
         format!(
@@ -187,5 +303,273 @@ fn handle_launch(args: ArgMatches, server_soc: String) {
         format!("open-here {}", program)
     };
 
-    send_data(payload, &server_soc);
+    send_data(payload, &server_soc)
+}
+
+/// prints the servers response the way `output` asks for and passes on the error code.
+fn print_response((ec, response): (ErrorCode, String), output: Output) -> ErrorCode {
+    match output {
+        Output::Plain if !response.is_empty() => println!("{response}"),
+        Output::Json => println!(
+            "{}",
+            serde_json::json!({
+                "code": ec,
+                "meaning": code_meaning(ec),
+                "message": response,
+            })
+        ),
+        _ => {}
+    }
+
+    ec
+}
+
+/// sends a command from the command registry, built from the sub-commands arguments.
+fn handle_command(
+    spec: &commands::CmdSpec,
+    args: &ArgMatches,
+    server_soc: &str,
+) -> (ErrorCode, String) {
+    let mut tokens = vec![spec.name.to_string()];
+
+    for arg in spec.args {
+        if let Some(values) = args.get_many::<String>(arg.name) {
+            tokens.extend(values.cloned());
+        }
+    }
+
+    send_data(tokens.join(" "), server_soc)
+}
+
+/// subscribes to the servers events and prints each one as a line of json until the server goes
+/// away. the usual response is only printed if the server refuses the subscription.
+fn handle_events(args: &ArgMatches, server_soc: &str, output: Output) -> ErrorCode {
+    let filters = args
+        .get_one::<String>("filter")
+        .cloned()
+        .unwrap_or_default();
+    let mut stream = match send_request(format!("{SUBSCRIBE_CMD} {filters}"), server_soc) {
+        Ok(stream) => stream,
+        Err(res) => return print_response(res, output),
+    };
+
+    let mut header = [0; 2];
+    if let Err(e) = stream.read_exact(&mut header) {
+        error!("reading server response resulted in error: \"{e}\"");
+        return print_response(
+            (
+                8,
+                format!("reading server response resulted in error: \"{e}\""),
+            ),
+            output,
+        );
+    }
+
+    if header[0] > 0 {
+        let mut message = String::new();
+        let _ = stream.read_to_string(&mut message);
+        return print_response((header[0], message), output);
+    }
+
+    for line in BufReader::new(stream).lines() {
+        match line {
+            Ok(line) => println!("{line}"),
+            Err(e) => {
+                error!("reading events from the server resulted in error: \"{e}\"");
+                return 8;
+            }
+        }
+    }
+
+    info!("the server closed the event stream");
+    0
+}
+
+/// formats a number of seconds as eg. "1h 2m 3s".
+fn format_uptime(secs: u64) -> String {
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s}s"),
+        (h, m, s) => format!("{h}h {m}m {s}s"),
+    }
+}
+
+/// asks the server for its status and formats the json it responds with for people to read.
+fn handle_status(server_soc: &str) -> (ErrorCode, String) {
+    let (ec, response) = send_data("status".to_string(), server_soc);
+    let status: serde_json::Value = match serde_json::from_str(&response) {
+        Ok(status) if ec == 0 => status,
+        _ => return (ec, response),
+    };
+    let field = |key: &str| match &status[key] {
+        serde_json::Value::String(value) => value.clone(),
+        serde_json::Value::Null => "N/A".to_string(),
+        value => value.to_string(),
+    };
+    let event_sources = status["event_sources"]
+        .as_array()
+        .map(|sources| {
+            sources
+                .iter()
+                .filter_map(|source| source.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .filter(|sources| !sources.is_empty())
+        .unwrap_or_else(|| "none".to_string());
+
+    let mut lines = vec![
+        format!("version:        {}", field("version")),
+        format!(
+            "uptime:         {}",
+            format_uptime(status["uptime"].as_u64().unwrap_or_default())
+        ),
+        format!("window manager: {}", field("wm")),
+        format!("wm socket:      {}", field("wm_socket")),
+        format!("layout:         {}", field("layout")),
+        format!("event sources:  {event_sources}"),
+        format!(
+            "port-sentinel:  {}",
+            match status["port_sentinel"].as_u64() {
+                Some(ago) => format!("last reported {} ago", format_uptime(ago)),
+                None => "hasn't reported".to_string(),
+            }
+        ),
+    ];
+
+    if let Some(qtile) = status.get("qtile") {
+        lines.push(format!("qtile rules:    {}", qtile["rules"]));
+        lines.push(format!("qtile queue:    {}", qtile["queue"]));
+        lines.push(format!("qtile clear:    {}", qtile["clear"]));
+    }
+
+    (ec, lines.join("\n"))
+}
+
+/// sends a command to the server exactly as the user typed it.
+fn handle_raw(args: &ArgMatches, server_soc: &str) -> (ErrorCode, String) {
+    let command = args.get_one::<String>("command").unwrap().clone();
+    send_data(command, server_soc)
+}
+
+/// the shells that `completions` can generate scripts for.
+pub const COMPLETION_SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+/// the hidden sub-command the completion scripts call to get dynamic candidates.
+pub const COMPLETE_CMD: &str = "__complete";
+
+/// prints completion candidates of `kind`, one per line. layouts are found the same way
+/// `load-layout` finds them, desktops are asked for from the running server.
+fn handle_complete(args: &ArgMatches, server_soc: &str) -> (ErrorCode, String) {
+    match args.get_one::<String>("kind").map(|kind| kind.as_str()) {
+        Some("layouts") => (0, wm_lib::list_layouts().join("\n")),
+        // an error's message isn't a desktop, so nothing is offered then.
+        Some("desktops") => match send_data("query-desktops".to_string(), server_soc) {
+            (0, desktops) => (0, desktops),
+            (ec, _) => (ec, String::new()),
+        },
+        _ => (7, String::new()),
+    }
+}
+
+// the completion scripts clap generates only know about static values. these get appended to
+// them so layout names and desktops are completed from `auto-desk __complete`.
+const BASH_DYNAMIC: &str = r#"
+_auto_desk_dynamic() {
+    local cur prev
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+
+    if [[ "${COMP_WORDS[1]}" == "layout" && "${cur}" != -* && ${COMP_CWORD} -eq 2 ]]; then
+        COMPREPLY=( $(compgen -W "$(auto-desk __complete layouts 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+
+    if [[ "${COMP_WORDS[1]}" == "launch" && ( "${prev}" == "-d" || "${prev}" == "--desktop" ) ]]; then
+        COMPREPLY=( $(compgen -W "$(auto-desk __complete desktops 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+
+    _auto-desk "$@"
+}
+
+complete -F _auto_desk_dynamic -o bashdefault -o default auto-desk
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_auto_desk_layouts() {
+    local -a layouts
+    layouts=(${(f)"$(auto-desk __complete layouts 2>/dev/null)"})
+    _describe 'layout' layouts
+}
+
+_auto_desk_desktops() {
+    local -a desktops
+    desktops=(${(f)"$(auto-desk __complete desktops 2>/dev/null)"})
+    _describe 'desktop' desktops
+}
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+complete -c auto-desk -n "__fish_seen_subcommand_from layout" -f -a "(auto-desk __complete layouts 2>/dev/null)"
+complete -c auto-desk -n "__fish_seen_subcommand_from launch" -s d -l desktop -x -a "(auto-desk __complete desktops 2>/dev/null)"
+"#;
+
+/// makes zsh complete layout and desktop arguments with the `_auto_desk_*` functions from
+/// `ZSH_DYNAMIC`. they have to be defined before the script calls `_auto-desk`.
+fn add_zsh_dynamic(script: &str) -> String {
+    let layouts = Regex::new(r"('[*]?:layout -- [^:']*:)'").unwrap();
+    let desktops = Regex::new(r"('[*]?:desktop -- [^:']*:|:TARGET-DESKTOP:) ?'").unwrap();
+
+    let script = layouts.replace_all(script, "${1}_auto_desk_layouts'");
+    let script = desktops.replace_all(&script, "${1}_auto_desk_desktops'");
+
+    script.replacen(
+        "\n_auto-desk \"$@\"",
+        &format!("{ZSH_DYNAMIC}\n_auto-desk \"$@\""),
+        1,
+    )
+}
+
+/// prints the completion script for `shell` generated from the clap definitions in `cli`.
+pub fn print_completions(cli: &mut App<'static>, shell: &str) -> ErrorCode {
+    let mut script = Vec::new();
+
+    let shell = match shell {
+        "bash" => Shell::Bash,
+        "zsh" => Shell::Zsh,
+        "fish" => Shell::Fish,
+        _ => {
+            error!("can't make completions for \"{shell}\"");
+            return 7;
+        }
+    };
+
+    generate(shell, cli, "auto-desk", &mut script);
+    let script = String::from_utf8_lossy(&script);
+
+    match shell {
+        Shell::Zsh => print!("{}", add_zsh_dynamic(&script)),
+        Shell::Fish => print!("{script}{FISH_DYNAMIC}"),
+        _ => print!("{script}{BASH_DYNAMIC}"),
+    }
+
+    0
+}
+
+#[test]
+fn test_format_uptime() {
+    assert_eq!(format_uptime(59), "59s");
+    assert_eq!(format_uptime(61), "1m 1s");
+    assert_eq!(format_uptime(3723), "1h 2m 3s");
+}
+
+#[test]
+fn test_add_zsh_dynamic() {
+    let script = "'-d+[desktop]:TARGET-DESKTOP: ' \\\n':layout -- the layout file to use:' \\\n\n_auto-desk \"$@\"\n";
+    let script = add_zsh_dynamic(script);
+
+    assert!(script.contains(":TARGET-DESKTOP:_auto_desk_desktops'"));
+    assert!(script.contains(":layout -- the layout file to use:_auto_desk_layouts'"));
+    assert!(script.find("_auto_desk_layouts() {") < script.find("\n_auto-desk \"$@\""));
 }
@@ -0,0 +1,485 @@
+use crate::config::GenericRes;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct CmdArg {
+    pub name: &'static str,
+    pub help: &'static str,
+    /// takes all the remaining words of the command. (eg. a shell command)
+    pub rest: bool,
+    pub required: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CmdSpec {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub args: &'static [CmdArg],
+    /// the modules that handle this command. (eg. "media", or "bspwm" & "leftwm")
+    pub modules: &'static [&'static str],
+    /// false if the cargo feature that provides the command is turned off.
+    pub enabled: bool,
+}
+
+const fn arg(name: &'static str, help: &'static str) -> CmdArg {
+    CmdArg {
+        name,
+        help,
+        rest: false,
+        required: true,
+    }
+}
+
+const fn opt(name: &'static str, help: &'static str) -> CmdArg {
+    CmdArg {
+        name,
+        help,
+        rest: false,
+        required: false,
+    }
+}
+
+const fn rest(name: &'static str, help: &'static str) -> CmdArg {
+    CmdArg {
+        name,
+        help,
+        rest: true,
+        required: true,
+    }
+}
+
+const fn cmd(
+    name: &'static str,
+    about: &'static str,
+    args: &'static [CmdArg],
+    modules: &'static [&'static str],
+    enabled: bool,
+) -> CmdSpec {
+    CmdSpec {
+        name,
+        about,
+        args,
+        modules,
+        enabled,
+    }
+}
+
+/// the modules that only handle commands while their window manager is running.
+pub const WM_MODULES: &[&str] = &[
+    "bspwm",
+    "leftwm",
+    "qtile",
+    "i3",
+    "sway",
+    "herbstluftwm",
+    "ewmh",
+    "hyprland",
+    "mock",
+];
+
+/// whether a window manager's feature is turned on, which the window manager commands need. the
+/// mock window manager handles all of them, so none depends on a particular window manager.
+const WM_ENABLED: bool = cfg!(any(
+    feature = "bspwm",
+    feature = "leftwm",
+    feature = "qtile",
+    feature = "i3",
+    feature = "sway",
+    feature = "herbstluftwm",
+    feature = "ewmh",
+    feature = "hyprland"
+));
+
+const SERVER: &[&str] = &["server"];
+const HOOKS: &[&str] = &["hooks"];
+const COMMON: &[&str] = &["common"];
+const SYSTEMCTL: &[&str] = &["systemctl"];
+const MEDIA: &[&str] = &["media"];
+const BLUETOOTH: &[&str] = &["bluetooth"];
+const QTILE: &[&str] = &["qtile"];
+/// the window managers that can send desktops to monitors.
+const MONITOR_WMS: &[&str] = &[
+    "bspwm",
+    "leftwm",
+    "i3",
+    "sway",
+    "herbstluftwm",
+    "hyprland",
+    "mock",
+];
+
+const PERCENT: &[CmdArg] = &[arg("percent", "the amount to change by, in percent")];
+const DESKTOP: &[CmdArg] = &[arg("desktop", "the target desktop")];
+const LAYOUT: &[CmdArg] = &[arg("layout", "the layout file to use")];
+const ADDRESS: &[CmdArg] = &[arg("address", "the bluetooth address of the device")];
+
+/// the registry of every command the server understands. the client builds its sub-commands
+/// from this list, so a command added to one of the `*_switch` functions belongs here too.
+pub const COMMANDS: &[CmdSpec] = &[
+    cmd(
+        "list-commands",
+        "lists the commands the server knows as json (also \"help\")",
+        &[opt("command", "only describe this command")],
+        SERVER,
+        true,
+    ),
+    cmd(
+        "status",
+        "reports the detected wm, the loaded layout, and which event sources are running",
+        &[],
+        SERVER,
+        true,
+    ),
+    cmd(
+        "reload-config",
+        "reloads the config file, the old config is kept if the new one is invalid",
+        &[],
+        SERVER,
+        true,
+    ),
+    cmd(
+        "rescan-wm",
+        "detects the running window manager again and responds with its name",
+        &[],
+        SERVER,
+        true,
+    ),
+    cmd(
+        "subscribe",
+        "streams events as lines of json (see `auto-desk events`)",
+        &[opt("filters", "comma separated events to stream")],
+        HOOKS,
+        cfg!(feature = "hooks"),
+    ),
+    cmd(
+        "open-here",
+        "runs a shell command (or launches a .desktop file)",
+        &[rest("program", "the command to run")],
+        COMMON,
+        cfg!(feature = "common"),
+    ),
+    cmd(
+        "screen-shot",
+        "presses the print screen key",
+        &[],
+        COMMON,
+        cfg!(feature = "common"),
+    ),
+    cmd(
+        "inc-bl",
+        "increases the backlight brightness",
+        PERCENT,
+        COMMON,
+        cfg!(feature = "common"),
+    ),
+    cmd(
+        "dec-bl",
+        "decreases the backlight brightness",
+        PERCENT,
+        COMMON,
+        cfg!(feature = "common"),
+    ),
+    cmd(
+        "add-monitor",
+        "turns a monitor on via xrandr",
+        &[arg("monitor", "the xrandr output name")],
+        COMMON,
+        cfg!(feature = "common"),
+    ),
+    cmd(
+        "battery",
+        "reports battery charge and AC status",
+        &[],
+        COMMON,
+        cfg!(feature = "common"),
+    ),
+    cmd(
+        "poweroff",
+        "powers off the system",
+        &[],
+        SYSTEMCTL,
+        cfg!(feature = "systemctl"),
+    ),
+    cmd(
+        "hibernate",
+        "hibernates the system",
+        &[],
+        SYSTEMCTL,
+        cfg!(feature = "systemctl"),
+    ),
+    cmd(
+        "reboot",
+        "reboots the system",
+        &[],
+        SYSTEMCTL,
+        cfg!(feature = "systemctl"),
+    ),
+    cmd(
+        "sleep",
+        "suspends the system",
+        &[],
+        SYSTEMCTL,
+        cfg!(feature = "systemctl"),
+    ),
+    cmd(
+        "suspend",
+        "suspends the system",
+        &[],
+        SYSTEMCTL,
+        cfg!(feature = "systemctl"),
+    ),
+    cmd(
+        "lock",
+        "locks the session",
+        &[],
+        SYSTEMCTL,
+        cfg!(feature = "systemctl"),
+    ),
+    cmd(
+        "logout",
+        "logs out of the session",
+        &[],
+        SYSTEMCTL,
+        cfg!(feature = "systemctl"),
+    ),
+    cmd(
+        "vol-up",
+        "raises the volume",
+        PERCENT,
+        MEDIA,
+        cfg!(feature = "media"),
+    ),
+    cmd(
+        "vol-down",
+        "lowers the volume",
+        PERCENT,
+        MEDIA,
+        cfg!(feature = "media"),
+    ),
+    cmd("mute", "toggles mute", &[], MEDIA, cfg!(feature = "media")),
+    cmd(
+        "play/pause",
+        "toggles media between play and pause",
+        &[],
+        MEDIA,
+        cfg!(feature = "media"),
+    ),
+    cmd(
+        "play-track",
+        "plays paused media",
+        &[],
+        MEDIA,
+        cfg!(feature = "media"),
+    ),
+    cmd(
+        "pause-track",
+        "pauses playing media",
+        &[],
+        MEDIA,
+        cfg!(feature = "media"),
+    ),
+    cmd(
+        "stop-track",
+        "stops the current media",
+        &[],
+        MEDIA,
+        cfg!(feature = "media"),
+    ),
+    cmd(
+        "next-track",
+        "skips to the next track",
+        &[],
+        MEDIA,
+        cfg!(feature = "media"),
+    ),
+    cmd(
+        "last-track",
+        "skips to the last track",
+        &[],
+        MEDIA,
+        cfg!(feature = "media"),
+    ),
+    cmd(
+        "bt-list",
+        "lists known bluetooth devices",
+        &[],
+        BLUETOOTH,
+        cfg!(feature = "bluetooth"),
+    ),
+    cmd(
+        "bt-connect",
+        "connects to a bluetooth device",
+        ADDRESS,
+        BLUETOOTH,
+        cfg!(feature = "bluetooth"),
+    ),
+    cmd(
+        "bt-disconnect",
+        "disconnects from a bluetooth device",
+        ADDRESS,
+        BLUETOOTH,
+        cfg!(feature = "bluetooth"),
+    ),
+    cmd(
+        "open-at",
+        "opens a program on a desktop. the argument order depends on the wm, see spec.md",
+        &[rest("args", "the program and desktop")],
+        WM_MODULES,
+        WM_ENABLED,
+    ),
+    cmd(
+        "focus-on",
+        "switches focus to a desktop",
+        DESKTOP,
+        WM_MODULES,
+        WM_ENABLED,
+    ),
+    cmd(
+        "move-to",
+        "moves the focused window to a desktop",
+        DESKTOP,
+        WM_MODULES,
+        WM_ENABLED,
+    ),
+    cmd(
+        "close-focused",
+        "closes the focused window",
+        &[],
+        WM_MODULES,
+        WM_ENABLED,
+    ),
+    cmd(
+        "load-layout",
+        "sets up the desktops described by a layout file",
+        LAYOUT,
+        WM_MODULES,
+        WM_ENABLED,
+    ),
+    cmd(
+        "apply-workspaces",
+        "moves desktops to monitors according to a layout's workspaces map",
+        LAYOUT,
+        MONITOR_WMS,
+        WM_ENABLED,
+    ),
+    cmd(
+        "query-desktops",
+        "lists the names of all desktops, one per line",
+        &[],
+        &[
+            "bspwm",
+            "leftwm",
+            "qtile",
+            "herbstluftwm",
+            "ewmh",
+            "hyprland",
+            "mock",
+        ],
+        WM_ENABLED,
+    ),
+    cmd(
+        "query-windows",
+        "lists windows as \"desktop<tab>title\", one per line",
+        &[],
+        &["leftwm", "qtile", "mock"],
+        WM_ENABLED,
+    ),
+    cmd(
+        "auto-move",
+        "asks where a window with the given wm-classes should go (used by auto_desk_api.py)",
+        &[
+            arg("instance", "the window's wm-class instance"),
+            arg("class", "the window's wm-class"),
+        ],
+        QTILE,
+        cfg!(feature = "qtile"),
+    ),
+    cmd(
+        "should-clear",
+        "asks if a group should be cleared (used by auto_desk_api.py)",
+        &[arg("group", "the qtile group")],
+        QTILE,
+        cfg!(feature = "qtile"),
+    ),
+];
+
+/// returns the spec of the command `name`, if it exists and its feature is turned on.
+pub fn get_command(name: &str) -> Option<&'static CmdSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.enabled && spec.name == name)
+}
+
+/// returns every command whose feature is turned on.
+pub fn enabled_commands() -> impl Iterator<Item = &'static CmdSpec> {
+    COMMANDS.iter().filter(|spec| spec.enabled)
+}
+
+/// returns true if the command `name` is only handled by window manager modules.
+pub fn needs_wm(name: &str) -> bool {
+    get_command(name).is_some_and(|spec| {
+        spec.modules
+            .iter()
+            .all(|module| WM_MODULES.contains(module))
+    })
+}
+
+/// returns true if `spec` can be run right now, ie. its feature is turned on and, if it's a
+/// window manager command, one of its window managers is the one running (`wm_module`).
+fn is_available(spec: &CmdSpec, wm_module: Option<&str>) -> bool {
+    spec.enabled
+        && spec
+            .modules
+            .iter()
+            .any(|module| !WM_MODULES.contains(module) || Some(*module) == wm_module)
+}
+
+fn describe(spec: &CmdSpec, wm_module: Option<&str>) -> serde_json::Value {
+    let mut description = serde_json::json!(spec);
+    description["available"] = is_available(spec, wm_module).into();
+    description
+}
+
+/// list-commands command. responds with a json list describing every command (or just the
+/// command `name`, if it's not empty), including whether it's available with the running
+/// window manager, `wm_module`.
+pub fn list_commands(wm_module: Option<&str>, name: &str) -> GenericRes {
+    if name.is_empty() {
+        let descriptions: Vec<_> = COMMANDS
+            .iter()
+            .map(|spec| describe(spec, wm_module))
+            .collect();
+        return (0, Some(serde_json::Value::from(descriptions).to_string()));
+    }
+
+    match COMMANDS.iter().find(|spec| spec.name == name) {
+        Some(spec) => (0, Some(describe(spec, wm_module).to_string())),
+        None => (
+            1,
+            Some(format!("there is no command by the name of, {name}")),
+        ),
+    }
+}
+
+#[test]
+fn test_command_names_unique() {
+    let mut names: Vec<&str> = COMMANDS.iter().map(|spec| spec.name).collect();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), COMMANDS.len());
+}
+
+#[test]
+fn test_list_commands() {
+    let (ec, message) = list_commands(None, "move-to");
+    let description: serde_json::Value = serde_json::from_str(&message.unwrap()).unwrap();
+
+    assert_eq!(ec, 0);
+    assert_eq!(description["args"][0]["name"], "desktop");
+    assert_eq!(description["available"], false);
+    assert!(list_commands(Some("leftwm"), "move-to")
+        .1
+        .unwrap()
+        .contains(&format!("\"available\":{WM_ENABLED}")));
+    assert_eq!(list_commands(None, "not-a-command").0, 1);
+}
@@ -1,11 +1,15 @@
 use crate::config::OptGenRes;
 use log::{error, info};
 use rdev::{simulate, EventType, Key, SimulateError};
+use std::path::Path;
 use std::process::{Command, Stdio};
 use std::{thread, time};
 
-mod backlight;
-mod media;
+pub mod backlight;
+pub mod battery;
+#[cfg(feature = "bluetooth")]
+pub mod bluetooth;
+pub mod media;
 mod power;
 mod xrandr;
 
@@ -83,6 +87,7 @@ pub async fn common_switch(cmd: &str, args: &str) -> OptGenRes {
         "inc-bl" => Some((backlight::inc_bright(args), None)),
         "dec-bl" => Some((backlight::dec_bright(args), None)),
         "add-monitor" => Some((xrandr::add_monitor(args), None)),
+        "battery" => Some(battery::query(Path::new(battery::POWER_SUPPLY_DIR))),
         _ => None,
     }
 }
@@ -115,3 +120,13 @@ pub async fn media_switch(cmd: &str, args: &str) -> OptGenRes {
         _ => None,
     }
 }
+
+#[cfg(feature = "bluetooth")]
+pub async fn bluetooth_switch(cmd: &str, args: &str) -> OptGenRes {
+    match cmd {
+        "bt-list" => Some(bluetooth::list().await),
+        "bt-connect" => Some(bluetooth::connect(args).await),
+        "bt-disconnect" => Some(bluetooth::disconnect(args).await),
+        _ => None,
+    }
+}
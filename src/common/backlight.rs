@@ -23,3 +23,18 @@ pub fn inc_bright(amount: &str) -> u8 {
 pub fn dec_bright(amount: &str) -> u8 {
     xbacklight("-dec", amount)
 }
+
+/// returns the current backlight brightness in percent.
+pub fn get_bright() -> Option<u8> {
+    match Command::new("xbacklight").arg("-get").output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .map(|bright| bright.round() as u8),
+        Err(e) => {
+            error!("could not read the backlight brightness. got error: \"{e}\"");
+            None
+        }
+    }
+}
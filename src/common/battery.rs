@@ -0,0 +1,132 @@
+use crate::config::GenericRes;
+use log::error;
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+
+pub const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Battery {
+    pub name: String,
+    /// charge in percent.
+    pub capacity: u8,
+    /// eg. "Charging", "Discharging", "Full".
+    pub status: String,
+}
+
+/// reads a single attribute file of a power supply. (eg. "BAT0/capacity")
+fn read_attr(supply: &Path, attr: &str) -> Option<String> {
+    read_to_string(supply.join(attr))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+/// returns the paths of all power supplies of type `supply_type` (eg. "Battery" or "Mains").
+fn supplies(root: &Path, supply_type: &str) -> Vec<std::path::PathBuf> {
+    let entries = match read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("could not read power supply dir {root:?}. got error: \"{e}\"");
+            return Vec::new();
+        }
+    };
+
+    let mut paths: Vec<_> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| read_attr(path, "type").as_deref() == Some(supply_type))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// returns every battery found under `root` (usually "/sys/class/power_supply").
+pub fn batteries(root: &Path) -> Vec<Battery> {
+    supplies(root, "Battery")
+        .into_iter()
+        .filter_map(|path| {
+            Some(Battery {
+                name: path.file_name()?.to_string_lossy().to_string(),
+                capacity: read_attr(&path, "capacity")?.parse().ok()?,
+                status: read_attr(&path, "status").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// returns whether an AC adapter is plugged in, or None if the system has no AC adapter.
+pub fn ac_online(root: &Path) -> Option<bool> {
+    let adapters = supplies(root, "Mains");
+
+    if adapters.is_empty() {
+        return None;
+    }
+
+    Some(
+        adapters
+            .iter()
+            .any(|path| read_attr(path, "online").as_deref() == Some("1")),
+    )
+}
+
+/// battery command. responds with one line per battery as "{name} {capacity}% {status}",
+/// followed by "AC online"/"AC offline".
+pub fn query(root: &Path) -> GenericRes {
+    let mut lines: Vec<String> = batteries(root)
+        .into_iter()
+        .map(|bat| format!("{} {}% {}", bat.name, bat.capacity, bat.status))
+        .collect();
+
+    match ac_online(root) {
+        Some(true) => lines.push("AC online".to_string()),
+        Some(false) => lines.push("AC offline".to_string()),
+        None => {}
+    }
+
+    if lines.is_empty() {
+        (4, Some("no batteries or AC adapters found".to_string()))
+    } else {
+        (0, Some(lines.join("\n")))
+    }
+}
+
+/// makes a fake "/sys/class/power_supply" dir for tests.
+#[cfg(test)]
+pub fn fake_power_supply(name: &str, supplies: &[(&str, &[(&str, &str)])]) -> std::path::PathBuf {
+    let root = std::env::temp_dir().join(format!("auto-desk-{name}-{}", std::process::id()));
+
+    for (supply, attrs) in supplies {
+        std::fs::create_dir_all(root.join(supply)).unwrap();
+        for (attr, value) in attrs.iter() {
+            std::fs::write(root.join(supply).join(attr), format!("{value}\n")).unwrap();
+        }
+    }
+
+    root
+}
+
+#[test]
+fn test_query() {
+    let root = fake_power_supply(
+        "query",
+        &[
+            (
+                "BAT0",
+                &[
+                    ("type", "Battery"),
+                    ("capacity", "42"),
+                    ("status", "Discharging"),
+                ],
+            ),
+            ("AC", &[("type", "Mains"), ("online", "0")]),
+        ],
+    );
+
+    let res = query(&root);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(
+        res,
+        (0, Some("BAT0 42% Discharging\nAC offline".to_string()))
+    );
+}
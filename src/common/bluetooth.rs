@@ -0,0 +1,127 @@
+use crate::config::GenericRes;
+use btleplug::api::{BDAddr, Central, Manager as _, Peripheral as _};
+use btleplug::platform::{Adapter, Manager, Peripheral};
+use log::{error, info};
+use std::str::FromStr;
+
+/// returns the first bluetooth adapter found on the system.
+pub async fn get_adapter() -> Result<Adapter, String> {
+    let manager = Manager::new()
+        .await
+        .map_err(|e| format!("could not connect to the bluetooth stack. got error: \"{e}\""))?;
+
+    match manager.adapters().await {
+        Ok(adapters) => adapters
+            .into_iter()
+            .next()
+            .ok_or_else(|| "no bluetooth adapter found".to_string()),
+        Err(e) => Err(format!(
+            "could not list bluetooth adapters. got error: \"{e}\""
+        )),
+    }
+}
+
+/// returns the name the device advertises, or an empty string if it has none.
+pub async fn get_name(peripheral: &Peripheral) -> String {
+    match peripheral.properties().await {
+        Ok(Some(props)) => props.local_name.unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+async fn find_peripheral(addr: &str) -> Result<Peripheral, GenericRes> {
+    let address = match BDAddr::from_str(addr.trim()) {
+        Ok(address) => address,
+        Err(e) => {
+            error!("\"{addr}\" is not a bluetooth address. got error: \"{e}\"");
+            return Err((7, Some(format!("\"{addr}\" is not a bluetooth address"))));
+        }
+    };
+
+    let adapter = get_adapter().await.map_err(|e| {
+        error!("{e}");
+        (4, Some(e))
+    })?;
+
+    let peripherals = adapter.peripherals().await.map_err(|e| {
+        error!("could not list bluetooth devices. got error: \"{e}\"");
+        (4, None)
+    })?;
+
+    match peripherals.into_iter().find(|p| p.address() == address) {
+        Some(peripheral) => Ok(peripheral),
+        None => Err((
+            4,
+            Some(format!("no known bluetooth device with address {address}")),
+        )),
+    }
+}
+
+/// lists known bluetooth devices, one per line, in the format "{address} {connected} {name}".
+pub async fn list() -> GenericRes {
+    let adapter = match get_adapter().await {
+        Ok(adapter) => adapter,
+        Err(e) => {
+            error!("{e}");
+            return (4, Some(e));
+        }
+    };
+
+    let peripherals = match adapter.peripherals().await {
+        Ok(peripherals) => peripherals,
+        Err(e) => {
+            error!("could not list bluetooth devices. got error: \"{e}\"");
+            return (4, None);
+        }
+    };
+
+    let mut devices = Vec::new();
+
+    for peripheral in peripherals {
+        let connected = peripheral.is_connected().await.unwrap_or(false);
+        devices.push(format!(
+            "{} {} {}",
+            peripheral.address(),
+            if connected {
+                "connected"
+            } else {
+                "disconnected"
+            },
+            get_name(&peripheral).await
+        ));
+    }
+
+    (0, Some(devices.join("\n")))
+}
+
+pub async fn connect(addr: &str) -> GenericRes {
+    let peripheral = match find_peripheral(addr).await {
+        Ok(peripheral) => peripheral,
+        Err(res) => return res,
+    };
+
+    info!("connecting to bluetooth device {addr}");
+    match peripheral.connect().await {
+        Ok(_) => (0, None),
+        Err(e) => {
+            error!("could not connect to bluetooth device {addr}. got error: \"{e}\"");
+            (4, Some(format!("{e}")))
+        }
+    }
+}
+
+pub async fn disconnect(addr: &str) -> GenericRes {
+    let peripheral = match find_peripheral(addr).await {
+        Ok(peripheral) => peripheral,
+        Err(res) => return res,
+    };
+
+    info!("disconnecting from bluetooth device {addr}");
+    match peripheral.disconnect().await {
+        Ok(_) => (0, None),
+        Err(e) => {
+            error!("could not disconnect from bluetooth device {addr}. got error: \"{e}\"");
+            (4, Some(format!("{e}")))
+        }
+    }
+}
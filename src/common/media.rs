@@ -35,6 +35,24 @@ pub fn volume_down(amount: &str) -> u8 {
     change_volume(amount, false)
 }
 
+/// pulls the volume (in percent) and whether it's muted out of the output of "amixer get Master".
+fn parse_amixer(output: &str) -> Option<(u8, bool)> {
+    let line = output.lines().find(|line| line.contains("%]"))?;
+    let level = line.split_once('[')?.1.split_once("%]")?.0.parse().ok()?;
+    Some((level, line.contains("[off]")))
+}
+
+/// returns the current volume in percent and whether it's muted.
+pub fn get_volume() -> Option<(u8, bool)> {
+    match Command::new("amixer").args(["get", "Master"]).output() {
+        Ok(output) => parse_amixer(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            error!("could not read the volume via amixer. got error: \"{e}\"");
+            None
+        }
+    }
+}
+
 pub fn mute() -> u8 {
     match Command::new("amixer")
         .args(["-D", "pulse", "set", "Master", "1+", "toggle"])
@@ -81,3 +99,13 @@ pub fn next_track() -> u8 {
 pub fn last_track() -> u8 {
     playerctl("previous")
 }
+
+#[test]
+fn test_parse_amixer() {
+    let output = "Simple mixer control 'Master',0\n  Capabilities: pvolume pswitch\n  \
+                  Front Left: Playback 39321 [60%] [off]\n  \
+                  Front Right: Playback 39321 [60%] [off]\n";
+    assert_eq!(parse_amixer(output), Some((60, true)));
+    assert_eq!(parse_amixer("Mono: Playback 5 [7%] [on]"), Some((7, false)));
+    assert_eq!(parse_amixer(""), None);
+}
@@ -2,7 +2,7 @@ use log::error;
 use std::process::{Command, Stdio};
 
 pub fn add_monitor(monitor: &str) -> u8 {
-    return match Command::new("xrandr")
+    match Command::new("xrandr")
         .args(["--output", monitor, "--auto"])
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -13,5 +13,5 @@ pub fn add_monitor(monitor: &str) -> u8 {
             error!("couldn't not add monitor via xrandr: {}", e);
             4
         }
-    };
+    }
 }
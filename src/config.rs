@@ -1,22 +1,78 @@
+use log::info;
 use serde::Deserialize;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::ErrorKind;
 
 pub const PORT_PIPE: &str = "auto-desk.ports";
 
-#[derive(Deserialize, Clone)]
+/// the values the `wm` setting (and the `--wm` flag) can have.
+pub const WM_SETTINGS: [&str; 11] = [
+    "auto",
+    "qtile",
+    "bspwm",
+    "leftwm",
+    "i3",
+    "sway",
+    "herbstluftwm",
+    "ewmh",
+    "hyprland",
+    "mock",
+    "none",
+];
+
+#[derive(Deserialize, Clone, Default)]
 pub struct Config {
+    #[serde(default)]
     pub server: Server,
+    #[serde(default)]
     pub hooks: Hooks,
+    #[serde(default)]
+    pub monitors: Monitors,
+    #[serde(default)]
+    pub usb: Usb,
+    #[serde(default)]
+    pub bluetooth: Bluetooth,
+    #[serde(default)]
+    pub network: Network,
+    #[serde(default)]
+    pub power_supply: PowerSupply,
+    #[serde(default)]
+    pub port_sentinel: PortSentinel,
+    #[serde(default)]
+    pub security: Security,
+    #[serde(default)]
+    pub wm_events: WmEvents,
 }
 
 #[derive(Deserialize, Clone)]
+#[serde(default)]
 pub struct Server {
     pub listen_socket: String,
     pub wm_socket: String,
+    /// the window manager to control, one of "auto", "qtile", "bspwm", "leftwm", "i3", "sway",
+    /// "herbstluftwm", "ewmh", "hyprland", "mock", or "none". "mock" only records what it's asked
+    /// to do (see `mock::MockWm`).
+    /// "auto" (the default) detects the running one.
+    pub wm: Option<String>,
+    /// reload the config whenever the file changes.
+    pub watch_config: Option<bool>,
 }
 
-#[derive(Deserialize, Clone)]
+impl Default for Server {
+    fn default() -> Server {
+        Server {
+            listen_socket: format!("{}/auto-desk.sock", get_runtime_d()),
+            wm_socket: std::env::var("BSPWM_SOCKET")
+                .unwrap_or_else(|_| "/tmp/bspwm_0_0-socket".to_string()),
+            wm: None,
+            watch_config: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
 pub struct Hooks {
     pub exec_ignore: HashSet<String>,
     pub ignore_web: bool,
@@ -24,10 +80,159 @@ pub struct Hooks {
     pub hooks: Vec<Hook>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Deserialize, Clone, Debug, PartialEq)]
 pub struct Hook {
     pub event: String, // TODO: see if i can make this an enum
+    #[serde(alias = "hook")]
     pub exec: String,
+    /// event data that has to match for the hook to run. (eg. `match = { vendor_id = 0x046d }`)
+    #[serde(default, rename = "match")]
+    pub filter: HashMap<String, toml::Value>,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct Monitors {
+    /// watch for monitors being plugged in or unplugged.
+    pub watch: Option<bool>,
+    /// run the matching entry from `profiles` when an output gets connected.
+    pub apply_profiles: Option<bool>,
+    /// re-apply the `workspaces` map of the loaded layout when outputs change.
+    pub reload_workspaces: Option<bool>,
+    /// output name => shell command that configures it (eg. an xrandr or autorandr call).
+    #[serde(default)]
+    pub profiles: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct Usb {
+    /// watch for usb devices being attached or detached.
+    pub watch: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct Bluetooth {
+    /// watch for bluetooth devices connecting or disconnecting.
+    pub watch: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct Network {
+    /// watch for network changes (connectivity, ip addresses, and wifi networks).
+    pub watch: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct PowerSupply {
+    /// watch for the AC adapter being plugged in/out and for the battery running low.
+    pub watch: Option<bool>,
+    /// battery percentages that publish a "battery-level" event. defaults to 15 and 5.
+    pub thresholds: Option<Vec<u8>>,
+}
+
+impl Config {
+    /// whether the config lets users other than `own_uid` connect, so the sockets have to be
+    /// open to them.
+    pub fn shares_sockets(&self, own_uid: u32) -> bool {
+        let other = |uid: Option<u32>| uid.is_some_and(|uid| uid != own_uid);
+
+        other(self.port_sentinel.uid)
+            || self.security.clients.iter().any(|client| other(client.uid))
+    }
+
+    /// checks for settings that parse but can't be used.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.server.listen_socket.is_empty() {
+            return Err("server.listen_socket can't be empty".to_string());
+        }
+
+        if let Some(wm) = &self.server.wm {
+            if !WM_SETTINGS.contains(&wm.as_str()) {
+                return Err(format!(
+                    "server.wm must be one of {}, not \"{wm}\"",
+                    WM_SETTINGS.join(", ")
+                ));
+            }
+        }
+
+        if let Some(thresholds) = &self.power_supply.thresholds {
+            if let Some(threshold) = thresholds.iter().find(|threshold| **threshold > 100) {
+                return Err(format!(
+                    "power_supply.thresholds are percentages, {threshold} is more than 100"
+                ));
+            }
+        }
+
+        for socket in &self.security.sockets {
+            if socket.path.is_empty() || socket.path == self.server.listen_socket {
+                return Err(format!(
+                    "security.sockets need a path other than server.listen_socket, not \"{}\"",
+                    socket.path
+                ));
+            }
+        }
+
+        if self
+            .security
+            .clients
+            .iter()
+            .any(|client| client.uid.is_none() && client.exe.is_none())
+        {
+            return Err("security.clients need a uid, an exe, or both".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct WmEvents {
+    /// publish the window manager's own events (eg. a window opening) where it can report them.
+    pub watch: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct PortSentinel {
+    /// listen for port-sentinel and publish the ports it reports as "port-status-change" events.
+    pub watch: Option<bool>,
+    /// the uid (other than root) that port-sentinel may run as.
+    pub uid: Option<u32>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Security {
+    /// extra sockets that only take the commands their policy allows. (eg. a read-only socket
+    /// for a status bar)
+    pub sockets: Vec<SocketPolicy>,
+    /// policies for clients, picked by their uid and/or executable.
+    pub clients: Vec<ClientPolicy>,
+}
+
+/// the commands a client may send. a command has to be in `allow` (when it's set) and can't be
+/// in `deny`. "*" stands for every command.
+#[derive(Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct Policy {
+    pub allow: Option<Vec<String>>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct SocketPolicy {
+    pub path: String,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ClientPolicy {
+    /// applies to clients running as this user. naming another user lets them connect, and
+    /// opens the sockets to them. (see `Config::shares_sockets`)
+    pub uid: Option<u32>,
+    /// applies to clients running this executable, either its full path or its file name.
+    pub exe: Option<String>,
+    #[serde(flatten)]
+    pub policy: Policy,
 }
 
 pub type GenericRes = (u8, Option<String>);
@@ -35,48 +240,111 @@ pub type OptGenRes = Option<GenericRes>;
 
 const CONFIG_FILE: &str = "~/.config/auto-desk/config.toml";
 
-pub fn get_configs() -> Result<Config, std::io::Error> {
-    let fname = shellexpand::tilde(CONFIG_FILE).to_string();
-    let toml_file: String = fs::read_to_string(fname)?;
-    Ok(toml::de::from_str(&toml_file)?)
+/// the default config, with every setting explained. printed by `auto-desk config default`.
+pub const DEFAULT_CONFIG: &str = include_str!("default-config.toml");
+
+/// returns the path of the config file.
+pub fn config_file() -> String {
+    shellexpand::tilde(CONFIG_FILE).to_string()
+}
+
+/// loads and checks the config file. if there is no config file every setting is left at its
+/// default.
+pub fn get_configs() -> Result<Config, String> {
+    load_configs(&config_file())
+}
+
+/// loads and checks the config file at `path`. (see `get_configs`)
+pub fn load_configs(path: &str) -> Result<Config, String> {
+    let toml_file = match fs::read_to_string(path) {
+        Ok(toml_file) => toml_file,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            info!("there is no config file at \"{path}\", using the default settings");
+            return Ok(Config::default());
+        }
+        Err(e) => return Err(format!("could not read the config file at \"{path}\": {e}")),
+    };
+
+    let configs: Config = toml::de::from_str(&toml_file)
+        .map_err(|e| format!("the config file at \"{path}\" is invalid: {e}"))?;
+    configs
+        .validate()
+        .map_err(|e| format!("the config file at \"{path}\" is invalid: {e}"))?;
+
+    Ok(configs)
+}
+
+/// returns the uid of the user running this process.
+pub fn current_uid() -> u32 {
+    // getuid can't fail.
+    unsafe { libc::getuid() }
 }
 
-/// returns the runtime dir to use for named pipes and stuff.  
+/// returns the runtime dir that the server socket goes in. "$XDG_RUNTIME_DIR/auto-desk" or,
+/// if that's not set, "/tmp/auto-desk-<uid>".
+pub fn get_runtime_d() -> String {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => format!("{dir}/auto-desk"),
+        _ => format!("/tmp/auto-desk-{}", current_uid()),
+    }
+}
+
+/// returns the runtime dir to use for named pipes and stuff.
 pub fn get_pipe_d() -> String {
-    // let file_name = "ports-data.pipe";
-    // match BaseDirectories::with_prefix("auto-desk") {
-    //     Ok(run_dir) => {
-    //         match run_dir.find_runtime_file(&file_name) {
-    //             Some(path) => Ok(path.to_string_lossy().to_string()),
-    //             None => {
-    //                 // println!(");
-    //                 let error_mesg = "[ERROR] Couldn't find the auto-desk ports-data.pipe file.";
-    //                 println!("{error_mesg}");
-    //                 Err(String::from(error_mesg))
-    //             }
-    //         }
-    //     }
-    //     Err(e) => {
-    //         let error_mesg = "[ERROR] Couldn't find the auto-desk run dir. got error: \n{e}";
-    //         println!("{error_mesg}");
-    //         Err(String::from(error_mesg))
-    //     }
-    // }
-
-    // match env::var("XDG_RUNTIME_DIR") {
-    //     Ok(dir) => {
-    //         format!("{dir}/auto-desk")
-    //         // Ok(format!("{dir}/auto-desk"))
-    //     }
-    //     Err(e) => {
-    //         let uid = users::get_current_uid();
-    //         format!("/run/user/{uid}/auto-desk")
-    //         // Err(format!("{e}")),
-    //     }
-    // }
-    String::from("/tmp")
+    get_runtime_d()
 }
 
 pub fn get_pipe_f() -> String {
     format!("{}/{PORT_PIPE}", get_pipe_d())
 }
+
+#[test]
+fn test_default_config() {
+    let configs: Config = toml::de::from_str(DEFAULT_CONFIG).unwrap();
+    let defaults = Config::default();
+
+    assert_eq!(configs.validate(), Ok(()));
+    assert_eq!(configs.server.wm.as_deref(), Some("auto"));
+    assert_eq!(configs.server.listen_socket, defaults.server.listen_socket);
+    assert_eq!(configs.power_supply.thresholds, Some(vec![15, 5]));
+}
+
+#[test]
+fn test_shares_sockets() {
+    let configs: Config =
+        toml::de::from_str("[[security.clients]]\nuid = 1001\ndeny = [\"*\"]\n").unwrap();
+
+    assert!(configs.shares_sockets(1000));
+    assert!(!configs.shares_sockets(1001));
+    assert!(!Config::default().shares_sockets(1000));
+}
+
+#[test]
+fn test_minimal_config() {
+    let configs: Config =
+        toml::de::from_str("[[hooks.hooks]]\nevent = \"usb-attached\"\nhook = \"true\"\n").unwrap();
+
+    assert_eq!(configs.hooks.hooks[0].exec, "true");
+    assert!(configs.server.listen_socket.ends_with("/auto-desk.sock"));
+}
+
+#[test]
+fn test_validate() {
+    let mut configs: Config = toml::de::from_str(
+        "[server]\nlisten_socket = \"/tmp/auto-desk\"\nwm_socket = \"\"\nwm = \"bspwm\"\n\
+         [hooks]\nexec_ignore = []\nignore_web = true\nhooks = []\n",
+    )
+    .unwrap();
+    assert_eq!(configs.validate(), Ok(()));
+
+    configs.server.wm = Some("dwm".to_string());
+    assert!(configs.validate().is_err());
+
+    configs.server.wm = None;
+    configs.security.clients.push(ClientPolicy {
+        uid: None,
+        exe: None,
+        policy: Policy::default(),
+    });
+    assert!(configs.validate().is_err());
+}
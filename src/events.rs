@@ -0,0 +1,91 @@
+use log::{debug, trace};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+
+#[cfg(feature = "bluetooth")]
+pub mod bluetooth;
+#[cfg(feature = "bspwm")]
+pub mod bspwm;
+#[cfg(feature = "hyprland")]
+pub mod hyprland;
+pub mod monitor;
+pub mod network;
+pub mod ports;
+pub mod power_supply;
+pub mod usb;
+
+pub type EventTx = broadcast::Sender<Event>;
+pub type EventRx = broadcast::Receiver<Event>;
+
+/// how many events can be waiting on a slow listener before it starts missing them.
+pub const EVENT_BUFFER: usize = 64;
+
+/// something that happened on the system that hooks (and other listeners) may care about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    pub event: String,
+    pub data: BTreeMap<String, String>,
+}
+
+impl Event {
+    pub fn new(event: &str) -> Event {
+        Event {
+            event: event.to_string(),
+            data: BTreeMap::new(),
+        }
+    }
+
+    /// adds a key value pair to the event data.
+    pub fn with(mut self, key: &str, value: &str) -> Event {
+        self.data.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// returns true if one of `filters` names this event. a filter names the event with the
+    /// same name and every event that starts with the filter and a "-" (eg. "volume" names
+    /// "volume-changed"). an empty list of filters names every event.
+    pub fn matches(&self, filters: &[String]) -> bool {
+        filters.is_empty()
+            || filters.iter().any(|filter| {
+                self.event == *filter
+                    || self
+                        .event
+                        .strip_prefix(filter.as_str())
+                        .is_some_and(|rest| rest.starts_with('-'))
+            })
+    }
+}
+
+/// splits a comma separated list of event filters. (eg. "volume,desktop,layout")
+pub fn parse_filters(filters: &str) -> Vec<String> {
+    filters
+        .split(',')
+        .map(|filter| filter.trim().to_string())
+        .filter(|filter| !filter.is_empty())
+        .collect()
+}
+
+/// makes a new event bus.
+pub fn new_bus() -> EventTx {
+    let (tx, _rx) = broadcast::channel(EVENT_BUFFER);
+    tx
+}
+
+/// sends an event to every listener. having no listeners is not an error.
+pub fn publish(tx: &EventTx, event: Event) {
+    debug!("publishing event: {event:?}");
+    if tx.send(event).is_err() {
+        trace!("no one is listening for events");
+    }
+}
+
+#[test]
+fn test_event_matches() {
+    let event = Event::new("volume-changed").with("level", "40");
+
+    assert!(event.matches(&[]));
+    assert!(event.matches(&parse_filters("desktop, volume")));
+    assert!(event.matches(&parse_filters("volume-changed")));
+    assert!(!event.matches(&parse_filters("vol,layout")));
+}
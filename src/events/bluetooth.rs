@@ -0,0 +1,59 @@
+use crate::common::bluetooth::{get_adapter, get_name};
+use crate::events::{publish, Event, EventTx};
+use btleplug::api::{Central, CentralEvent, Peripheral as _};
+use btleplug::platform::{Adapter, PeripheralId};
+use futures::StreamExt;
+use log::{error, info};
+
+/// makes a bluetooth event (eg. "bt-connected") describing the device with `id`.
+async fn bt_event(adapter: &Adapter, event: &str, id: &PeripheralId) -> Option<Event> {
+    match adapter.peripheral(id).await {
+        Ok(peripheral) => Some(
+            Event::new(event)
+                .with("address", &peripheral.address().to_string())
+                .with("name", &get_name(&peripheral).await),
+        ),
+        Err(e) => {
+            error!("could not look up bluetooth device {id:?}. got error: \"{e}\"");
+            None
+        }
+    }
+}
+
+/// listens to the bluetooth adapter and publishes "bt-connected"/"bt-disconnected" events.
+pub async fn watch(events: EventTx) {
+    let adapter = match get_adapter().await {
+        Ok(adapter) => adapter,
+        Err(e) => {
+            error!("{e}, not watching for bluetooth events");
+            return;
+        }
+    };
+
+    let mut bt_events = match adapter.events().await {
+        Ok(bt_events) => bt_events,
+        Err(e) => {
+            error!("could not listen for bluetooth events. got error: \"{e}\"");
+            return;
+        }
+    };
+
+    info!("watching for bluetooth devices connecting or disconnecting");
+
+    while let Some(central_event) = bt_events.next().await {
+        let event = match central_event {
+            CentralEvent::DeviceConnected(id) => bt_event(&adapter, "bt-connected", &id).await,
+            CentralEvent::DeviceDisconnected(id) => {
+                bt_event(&adapter, "bt-disconnected", &id).await
+            }
+            _ => None,
+        };
+
+        if let Some(event) = event {
+            info!("{} {}", event.event, event.data["address"]);
+            publish(&events, event);
+        }
+    }
+
+    info!("bluetooth event stream ended");
+}
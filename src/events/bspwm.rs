@@ -0,0 +1,64 @@
+use crate::bspwm::{desktop_name, make_api, node_classes, parse_event, BspwmEvent, SUBSCRIBE};
+use crate::events::{publish, Event, EventTx};
+use log::{error, info};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::task;
+
+/// turns a bspwm event into an event, looking up the names bspwm only gives as ids.
+fn to_event(spath: &str, event: BspwmEvent) -> Event {
+    match event {
+        BspwmEvent::NodeAdd { desktop, node } => {
+            let class = node_classes(spath, &node).into_iter().next();
+            Event::new("window-opened")
+                .with("window", &node)
+                .with("desktop", &desktop_name(spath, &desktop))
+                .with("class", &class.unwrap_or_default())
+        }
+        BspwmEvent::NodeRemove { node, .. } => Event::new("window-closed").with("window", &node),
+        BspwmEvent::DesktopFocus { desktop } => {
+            Event::new("desktop-focused").with("desktop", &desktop_name(spath, &desktop))
+        }
+    }
+}
+
+/// subscribes to bspwm on `spath` and publishes "desktop-focused", "window-opened", and
+/// "window-closed" events.
+pub async fn watch(events: EventTx, spath: String) {
+    let mut stream = match UnixStream::connect(&spath).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("could not connect to bspwm at {spath}. got error: \"{e}\"");
+            return;
+        }
+    };
+    if let Err(e) = stream.write_all(&make_api(SUBSCRIBE)).await {
+        error!("could not subscribe to bspwm's events. got error: \"{e}\"");
+        return;
+    }
+    info!("watching bspwm's events on {spath}");
+
+    let mut lines = BufReader::new(stream).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                let Some(event) = parse_event(&line) else {
+                    continue;
+                };
+                let tmp_spath = spath.clone();
+                match task::spawn_blocking(move || to_event(&tmp_spath, event)).await {
+                    Ok(event) => publish(&events, event),
+                    Err(e) => error!("could not read a bspwm event. got error: \"{e}\""),
+                }
+            }
+            Ok(None) => {
+                info!("bspwm closed the event subscription");
+                break;
+            }
+            Err(e) => {
+                error!("could not read bspwm's events. got error: \"{e}\"");
+                break;
+            }
+        }
+    }
+}
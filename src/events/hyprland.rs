@@ -0,0 +1,78 @@
+use crate::events::{publish, Event, EventTx};
+use crate::hyprland::{socket_dir, EVENT_SOCKET};
+use log::{error, info};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::UnixStream;
+
+/// turns a line from Hyprland's event socket, "{event}>>{data}", into an event. events that
+/// aren't published give None.
+fn parse_line(line: &str) -> Option<Event> {
+    let (event, data) = line.split_once(">>")?;
+
+    match event {
+        "workspace" => Some(Event::new("desktop-focused").with("desktop", data)),
+        "openwindow" => {
+            let mut fields = data.splitn(4, ',');
+            Some(
+                Event::new("window-opened")
+                    .with("window", fields.next()?)
+                    .with("desktop", fields.next()?)
+                    .with("class", fields.next()?)
+                    .with("title", fields.next().unwrap_or_default()),
+            )
+        }
+        "closewindow" => Some(Event::new("window-closed").with("window", data)),
+        _ => None,
+    }
+}
+
+/// reads Hyprland's event socket and publishes "desktop-focused", "window-opened", and
+/// "window-closed" events. returns right away if Hyprland isn't running.
+pub async fn watch(events: EventTx) {
+    let Some(socket) = socket_dir().map(|dir| dir.join(EVENT_SOCKET)) else {
+        info!("Hyprland isn't running, not watching its events");
+        return;
+    };
+
+    let stream = match UnixStream::connect(&socket).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("could not connect to Hyprland's event socket {socket:?}. got error: \"{e}\"");
+            return;
+        }
+    };
+    info!("watching Hyprland's events on {socket:?}");
+
+    let mut lines = BufReader::new(stream).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                if let Some(event) = parse_line(&line) {
+                    publish(&events, event);
+                }
+            }
+            Ok(None) => {
+                info!("Hyprland closed its event socket");
+                break;
+            }
+            Err(e) => {
+                error!("could not read Hyprland's events. got error: \"{e}\"");
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_line() {
+    let opened = parse_line("openwindow>>55d1c3a0,2,kitty,~: vim, notes").unwrap();
+
+    assert_eq!(opened.event, "window-opened");
+    assert_eq!(opened.data["class"], "kitty");
+    assert_eq!(opened.data["title"], "~: vim, notes");
+    assert_eq!(
+        parse_line("workspace>>web"),
+        Some(Event::new("desktop-focused").with("desktop", "web"))
+    );
+    assert_eq!(parse_line("activewindow>>kitty,~"), None);
+}
@@ -0,0 +1,140 @@
+use crate::config::Monitors;
+use crate::events::{publish, Event, EventTx};
+use log::{error, info, warn};
+use std::collections::BTreeSet;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+
+pub const DRM_DIR: &str = "/sys/class/drm";
+const POLL_TIME: Duration = Duration::from_millis(1000);
+
+/// turns a drm connector dir name (eg. "card0-HDMI-A-1") into an output name (eg. "HDMI-A-1").
+fn output_name(connector: &str) -> Option<String> {
+    match connector.split_once('-') {
+        Some((card, output)) if card.starts_with("card") && !output.is_empty() => {
+            Some(output.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// returns the names of all outputs that the kernel reports as connected.
+pub fn connected_outputs(drm_dir: &Path) -> BTreeSet<String> {
+    let mut outputs = BTreeSet::new();
+
+    let entries = match read_dir(drm_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("could not read drm dir {drm_dir:?}. got error: \"{e}\"");
+            return outputs;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let Some(output) = output_name(&entry.file_name().to_string_lossy()) else {
+            continue;
+        };
+
+        match read_to_string(entry.path().join("status")) {
+            Ok(status) if status.trim() == "connected" => {
+                outputs.insert(output);
+            }
+            _ => {}
+        }
+    }
+
+    outputs
+}
+
+/// runs the monitor profile configured for `output`, if any.
+async fn apply_profile(conf: &Monitors, output: &str) {
+    let Some(profile) = conf.profiles.get(output) else {
+        return;
+    };
+
+    info!("applying monitor profile for {output}: \"{profile}\"");
+
+    match Command::new("sh").arg("-c").arg(profile).status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => error!("monitor profile for {output} exited with status: {status}"),
+        Err(e) => error!("could not run monitor profile for {output}. got error: \"{e}\""),
+    }
+}
+
+/// polls `drm_dir` for outputs being plugged in or unplugged and publishes
+/// "monitor-connected"/"monitor-disconnected" events. when configured to, it also applies
+/// monitor profiles and asks the server to re-apply the loaded layouts workspaces.
+pub async fn watch(
+    drm_dir: PathBuf,
+    conf: Monitors,
+    events: EventTx,
+    actions: mpsc::Sender<String>,
+) {
+    info!("watching {drm_dir:?} for monitor hotplug events");
+    let mut outputs = connected_outputs(&drm_dir);
+
+    loop {
+        sleep(POLL_TIME).await;
+        let new_outputs = connected_outputs(&drm_dir);
+
+        if new_outputs == outputs {
+            continue;
+        }
+
+        for output in outputs.difference(&new_outputs) {
+            info!("monitor disconnected: {output}");
+            publish(
+                &events,
+                Event::new("monitor-disconnected").with("output", output),
+            );
+        }
+
+        for output in new_outputs.difference(&outputs) {
+            info!("monitor connected: {output}");
+            publish(
+                &events,
+                Event::new("monitor-connected").with("output", output),
+            );
+
+            if conf.apply_profiles.unwrap_or(false) {
+                apply_profile(&conf, output).await;
+            }
+        }
+
+        if conf.reload_workspaces.unwrap_or(false)
+            && actions.send("apply-workspaces".to_string()).await.is_err()
+        {
+            warn!("server is no longer listening for actions, can't re-apply workspaces");
+        }
+
+        outputs = new_outputs;
+    }
+}
+
+#[test]
+fn test_connected_outputs() {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    let drm_dir = std::env::temp_dir().join(format!("auto-desk-drm-{}", std::process::id()));
+    for (connector, status) in [
+        ("card0-eDP-1", "connected\n"),
+        ("card0-HDMI-A-1", "disconnected\n"),
+        ("card1-DP-2", "connected\n"),
+    ] {
+        create_dir_all(drm_dir.join(connector)).unwrap();
+        write(drm_dir.join(connector).join("status"), status).unwrap();
+    }
+    create_dir_all(drm_dir.join("card0")).unwrap();
+    create_dir_all(drm_dir.join("renderD128")).unwrap();
+
+    let outputs = connected_outputs(&drm_dir);
+    remove_dir_all(&drm_dir).unwrap();
+
+    assert_eq!(
+        outputs,
+        BTreeSet::from(["eDP-1".to_string(), "DP-2".to_string()])
+    );
+}
@@ -0,0 +1,235 @@
+use crate::events::{publish, Event, EventTx};
+use log::{error, info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::task;
+use tokio::time::{sleep, Duration};
+
+pub const PROC_ROUTE: &str = "/proc/net/route";
+pub const SYS_NET_DIR: &str = "/sys/class/net";
+const POLL_TIME: Duration = Duration::from_millis(2000);
+
+/// set once `iw` turns out to be missing, so it isn't looked for (and warned about) every poll.
+static NO_IW: AtomicBool = AtomicBool::new(false);
+
+/// a snapshot of the network state of the system.
+#[derive(Debug, Default, PartialEq, Clone)]
+struct NetState {
+    /// the interface that the default route goes through, if there is one.
+    default_iface: Option<String>,
+    /// interface => the ip addresses it has.
+    ips: BTreeMap<String, BTreeSet<String>>,
+    /// wireless interface => the ssid it's connected to (empty if it's not connected).
+    ssids: BTreeMap<String, String>,
+}
+
+/// returns the interface that the default route goes through, read from a file in the
+/// format of "/proc/net/route".
+fn default_iface(route_file: &Path) -> Option<String> {
+    let routes = read_to_string(route_file).ok()?;
+
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            [iface, "00000000", ..] => Some(iface.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// returns the names of the wireless interfaces in `sys_net_dir`.
+fn wireless_ifaces(sys_net_dir: &Path) -> Vec<String> {
+    let Ok(entries) = sys_net_dir.read_dir() else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| entry.path().join("wireless").exists())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect()
+}
+
+/// pulls the ssid out of the output of "iw dev <iface> link".
+fn parse_ssid(iw_link: &str) -> String {
+    iw_link
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("SSID: "))
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// returns the ssid that `iface` is connected to. empty if not connected or if `iw` is missing.
+/// (the kernel only reports the ssid over nl80211, `iw` is what speaks it.)
+fn get_ssid(iface: &str) -> String {
+    if NO_IW.load(Ordering::Relaxed) {
+        return String::new();
+    }
+
+    match Command::new("iw").args(["dev", iface, "link"]).output() {
+        Ok(output) => parse_ssid(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            warn!("iw isn't installed, wifi-network-change events won't be published");
+            NO_IW.store(true, Ordering::Relaxed);
+            String::new()
+        }
+        Err(e) => {
+            error!("could not get the ssid of {iface} via iw. got error: \"{e}\"");
+            String::new()
+        }
+    }
+}
+
+fn get_ips() -> BTreeMap<String, BTreeSet<String>> {
+    let mut ips: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+    match local_ip_address::linux::list_afinet_netifas() {
+        Ok(netifas) => {
+            for (iface, ip) in netifas {
+                ips.entry(iface).or_default().insert(ip.to_string());
+            }
+        }
+        Err(e) => error!("could not list ip addresses. got error: \"{e}\""),
+    }
+
+    ips
+}
+
+fn get_state(route_file: &Path, sys_net_dir: &Path) -> NetState {
+    NetState {
+        default_iface: default_iface(route_file),
+        ips: get_ips(),
+        ssids: wireless_ifaces(sys_net_dir)
+            .into_iter()
+            .map(|iface| {
+                let ssid = get_ssid(&iface);
+                (iface, ssid)
+            })
+            .collect(),
+    }
+}
+
+/// `get_state` on a blocking thread, since reading the ssid runs `iw`.
+async fn poll_state(route_file: &Path, sys_net_dir: &Path) -> Option<NetState> {
+    let (route_file, sys_net_dir) = (route_file.to_path_buf(), sys_net_dir.to_path_buf());
+
+    task::spawn_blocking(move || get_state(&route_file, &sys_net_dir))
+        .await
+        .inspect_err(|e| error!("could not read the network state. got error: \"{e}\""))
+        .ok()
+}
+
+/// compares two network snapshots and returns the events that describe the difference.
+fn diff_states(old: &NetState, new: &NetState) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    match (&old.default_iface, &new.default_iface) {
+        (None, Some(iface)) => events.push(Event::new("network-online").with("interface", iface)),
+        (Some(iface), None) => events.push(Event::new("network-offline").with("interface", iface)),
+        _ => {}
+    }
+
+    let ifaces: BTreeSet<&String> = old.ips.keys().chain(new.ips.keys()).collect();
+    let no_ips = BTreeSet::new();
+
+    for iface in ifaces {
+        let old_ips = old.ips.get(iface).unwrap_or(&no_ips);
+        let new_ips = new.ips.get(iface).unwrap_or(&no_ips);
+
+        if old_ips != new_ips {
+            events.push(
+                Event::new("ip-changed")
+                    .with("interface", iface)
+                    .with("ips", &Vec::from_iter(new_ips.iter().cloned()).join(","))
+                    .with(
+                        "old_ips",
+                        &Vec::from_iter(old_ips.iter().cloned()).join(","),
+                    ),
+            );
+        }
+    }
+
+    for (iface, ssid) in &new.ssids {
+        let old_ssid = old.ssids.get(iface).cloned().unwrap_or_default();
+
+        if &old_ssid != ssid {
+            events.push(
+                Event::new("wifi-network-change")
+                    .with("interface", iface)
+                    .with("ssid", ssid)
+                    .with("old_ssid", &old_ssid),
+            );
+        }
+    }
+
+    events
+}
+
+/// polls "/proc/net" and "/sys/class/net" and publishes "network-online", "network-offline",
+/// "ip-changed" and "wifi-network-change" events.
+pub async fn watch(route_file: PathBuf, sys_net_dir: PathBuf, events: EventTx) {
+    info!("watching for network changes");
+    let mut state = poll_state(&route_file, &sys_net_dir)
+        .await
+        .unwrap_or_default();
+
+    loop {
+        sleep(POLL_TIME).await;
+        let Some(new_state) = poll_state(&route_file, &sys_net_dir).await else {
+            continue;
+        };
+
+        for event in diff_states(&state, &new_state) {
+            info!("network event: {} {:?}", event.event, event.data);
+            publish(&events, event);
+        }
+
+        state = new_state;
+    }
+}
+
+#[test]
+fn test_default_iface() {
+    let route_file = std::env::temp_dir().join(format!("auto-desk-route-{}", std::process::id()));
+    std::fs::write(
+        &route_file,
+        "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+         wlan0\t0002A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0\n\
+         wlan0\t00000000\t0102A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n",
+    )
+    .unwrap();
+
+    let iface = default_iface(&route_file);
+    std::fs::remove_file(&route_file).unwrap();
+
+    assert_eq!(iface, Some("wlan0".to_string()));
+}
+
+#[test]
+fn test_diff_states() {
+    let iw_link = "Connected to 00:11:22:33:44:55 (on wlan0)\n\tSSID: home-wifi\n\tfreq: 5180\n";
+    let old = NetState::default();
+    let new = NetState {
+        default_iface: Some("wlan0".to_string()),
+        ips: BTreeMap::from([(
+            "wlan0".to_string(),
+            BTreeSet::from(["192.168.0.2".to_string()]),
+        )]),
+        ssids: BTreeMap::from([("wlan0".to_string(), parse_ssid(iw_link))]),
+    };
+
+    let events = diff_states(&old, &new);
+    let names: Vec<&str> = events.iter().map(|e| e.event.as_str()).collect();
+
+    assert_eq!(
+        names,
+        ["network-online", "ip-changed", "wifi-network-change"]
+    );
+    assert_eq!(events[1].data["ips"], "192.168.0.2");
+    assert_eq!(events[2].data["ssid"], "home-wifi");
+    assert!(diff_states(&new, &new).is_empty());
+}
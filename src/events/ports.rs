@@ -0,0 +1,93 @@
+use crate::events::{publish, Event, EventTx};
+use crate::{MSG_DELIM, MSG_SUCCESS};
+use log::{error, info, warn};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::net::UnixListener;
+
+/// when port-sentinel last connected, for the "status" command.
+static LAST_REPORT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// how long ago port-sentinel last connected. None if it hasn't since the server started.
+pub fn last_report() -> Option<Duration> {
+    LAST_REPORT.lock().unwrap().map(|at| at.elapsed())
+}
+
+/// turns a message from port-sentinel into a "port-status-change" event. messages are in the
+/// format of "{success}{delim}{pid}{delim}{local ip}{delim}{local port}{delim}{remote ip}{delim}
+/// {remote port}{delim}{direction}". error messages give None.
+fn parse_message(message: &str) -> Option<Event> {
+    let fields: Vec<&str> = message.split(MSG_DELIM).collect();
+
+    match fields[..] {
+        [status, pid, local_ip, local_port, remote_ip, remote_port, direction]
+            if status.starts_with(MSG_SUCCESS) =>
+        {
+            Some(
+                Event::new("port-status-change")
+                    .with("pid", pid)
+                    .with("local_ip", local_ip)
+                    .with("local_port", local_port)
+                    .with("remote_ip", remote_ip)
+                    .with("remote_port", remote_port)
+                    .with("direction", direction),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// accepts connections from port-sentinel and publishes a "port-status-change" event for each
+/// port it reports. only root and `uid` are allowed to connect.
+pub async fn watch(listener: UnixListener, uid: Option<u32>, events: EventTx) {
+    info!("listening for port-sentinel");
+
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _addr)) => stream,
+            Err(e) => {
+                error!("could not accept a port-sentinel connection. got error: \"{e}\"");
+                continue;
+            }
+        };
+
+        let peer = stream.peer_cred().ok().map(|cred| cred.uid());
+        if !matches!(peer, Some(peer) if peer == 0 || Some(peer) == uid) {
+            warn!("refused a port-sentinel connection from uid {peer:?}");
+            continue;
+        }
+
+        let mut message = String::new();
+        if let Err(e) = stream.read_to_string(&mut message).await {
+            error!("could not read from port-sentinel. got error: \"{e}\"");
+            continue;
+        }
+
+        *LAST_REPORT.lock().unwrap() = Some(Instant::now());
+
+        match parse_message(&message) {
+            Some(event) => publish(&events, event),
+            None => error!("port-sentinel reported: {message:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_parse_message() {
+    let message = [
+        "\0",
+        "1234",
+        "127.0.0.1",
+        "8080",
+        "127.0.0.1",
+        "50000",
+        "LOCAL",
+    ]
+    .join(&MSG_DELIM.to_string());
+    let event = parse_message(&message).unwrap();
+
+    assert_eq!(event.data["local_port"], "8080");
+    assert_eq!(event.data["direction"], "LOCAL");
+    assert_eq!(parse_message("\x07\x01no ports could be found"), None);
+}
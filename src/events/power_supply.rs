@@ -0,0 +1,128 @@
+use crate::common::battery::{ac_online, batteries};
+use crate::events::{publish, Event, EventTx};
+use log::info;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::time::{sleep, Duration};
+
+const POLL_TIME: Duration = Duration::from_millis(5000);
+
+/// keeps track of what has already been reported so each change is only published once.
+#[derive(Debug, Default)]
+struct PowerState {
+    ac: Option<bool>,
+    /// battery name => the lowest threshold that has been reported for it.
+    reported: HashMap<String, u8>,
+}
+
+impl PowerState {
+    /// reads the power supplies under `root` and returns the events that happened since the
+    /// last check.
+    fn check(&mut self, root: &Path, thresholds: &[u8]) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        let ac = ac_online(root);
+        match (self.ac, ac) {
+            (Some(false), Some(true)) => events.push(Event::new("ac-connected")),
+            (Some(true), Some(false)) => events.push(Event::new("ac-disconnected")),
+            _ => {}
+        }
+        self.ac = ac;
+
+        let highest = thresholds.iter().max().copied().unwrap_or(0);
+
+        for bat in batteries(root) {
+            if bat.status == "Charging" || bat.capacity > highest {
+                self.reported.remove(&bat.name);
+                continue;
+            }
+
+            let Some(threshold) = thresholds.iter().filter(|t| bat.capacity <= **t).min() else {
+                continue;
+            };
+
+            if self
+                .reported
+                .get(&bat.name)
+                .is_none_or(|reported| threshold < reported)
+            {
+                self.reported.insert(bat.name.clone(), *threshold);
+                events.push(
+                    Event::new("battery-level")
+                        .with("battery", &bat.name)
+                        .with("level", &bat.capacity.to_string())
+                        .with("threshold", &threshold.to_string())
+                        .with("status", &bat.status),
+                );
+            }
+        }
+
+        events
+    }
+}
+
+/// polls `root` (usually "/sys/class/power_supply") and publishes "ac-connected",
+/// "ac-disconnected", and "battery-level" events. a "battery-level" event is published once
+/// for each threshold the battery drops to while not charging.
+pub async fn watch(root: PathBuf, thresholds: Vec<u8>, events: EventTx) {
+    info!("watching {root:?} for power supply changes. battery thresholds: {thresholds:?}");
+    let mut state = PowerState::default();
+
+    loop {
+        for event in state.check(&root, &thresholds) {
+            info!("power supply event: {} {:?}", event.event, event.data);
+            publish(&events, event);
+        }
+
+        sleep(POLL_TIME).await;
+    }
+}
+
+#[test]
+fn test_power_state() {
+    use crate::common::battery::fake_power_supply;
+    use std::fs::{remove_dir_all, write};
+
+    let root = fake_power_supply(
+        "power-state",
+        &[
+            (
+                "BAT0",
+                &[
+                    ("type", "Battery"),
+                    ("capacity", "50"),
+                    ("status", "Discharging"),
+                ],
+            ),
+            ("AC", &[("type", "Mains"), ("online", "1")]),
+        ],
+    );
+    let thresholds = [15, 5];
+    let mut state = PowerState::default();
+    let mut check = |capacity: &str, status: &str, online: &str| {
+        write(root.join("BAT0/capacity"), capacity).unwrap();
+        write(root.join("BAT0/status"), status).unwrap();
+        write(root.join("AC/online"), online).unwrap();
+        state
+            .check(&root, &thresholds)
+            .into_iter()
+            .map(|event| match event.data.get("threshold") {
+                Some(threshold) => format!("{} {threshold}", event.event),
+                None => event.event,
+            })
+            .collect::<Vec<String>>()
+    };
+
+    assert!(check("50", "Discharging", "1").is_empty());
+    assert_eq!(check("50", "Discharging", "0"), ["ac-disconnected"]);
+    assert_eq!(check("14", "Discharging", "0"), ["battery-level 15"]);
+    assert!(check("13", "Discharging", "0").is_empty());
+    assert_eq!(check("4", "Discharging", "0"), ["battery-level 5"]);
+    assert_eq!(check("4", "Charging", "1"), ["ac-connected"]);
+    assert_eq!(
+        check("3", "Discharging", "0"),
+        ["ac-disconnected", "battery-level 5"]
+    );
+
+    remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,70 @@
+use crate::events::{publish, Event, EventTx};
+use log::{error, info};
+use std::collections::HashSet;
+use tokio::task::spawn_blocking;
+use tokio::time::{sleep, Duration};
+use usb_enumeration::UsbDevice;
+
+const POLL_TIME: Duration = Duration::from_millis(1000);
+
+/// makes a usb event (eg. "usb-attached") describing `device`.
+fn usb_event(event: &str, device: &UsbDevice) -> Event {
+    Event::new(event)
+        .with("id", &device.id)
+        .with("vendor_id", &format!("{:#06x}", device.vendor_id))
+        .with("product_id", &format!("{:#06x}", device.product_id))
+        .with(
+            "description",
+            device.description.as_deref().unwrap_or_default(),
+        )
+}
+
+/// lists the usb devices that are currently plugged in.
+async fn get_devices() -> HashSet<UsbDevice> {
+    match spawn_blocking(|| usb_enumeration::enumerate(None, None)).await {
+        Ok(devices) => devices.into_iter().collect(),
+        Err(e) => {
+            error!("enumerating usb devices failed. got error: \"{e}\"");
+            HashSet::new()
+        }
+    }
+}
+
+/// polls the usb bus and publishes "usb-attached"/"usb-detached" events.
+pub async fn watch(events: EventTx) {
+    info!("watching for usb devices being attached or detached");
+    let mut devices = get_devices().await;
+
+    loop {
+        sleep(POLL_TIME).await;
+        let new_devices = get_devices().await;
+
+        for device in devices.difference(&new_devices) {
+            info!("usb device detached: {device:?}");
+            publish(&events, usb_event("usb-detached", device));
+        }
+
+        for device in new_devices.difference(&devices) {
+            info!("usb device attached: {device:?}");
+            publish(&events, usb_event("usb-attached", device));
+        }
+
+        devices = new_devices;
+    }
+}
+
+#[test]
+fn test_usb_event() {
+    let device = UsbDevice {
+        id: "/sys/bus/usb/devices/1-2".to_string(),
+        vendor_id: 0x046d,
+        product_id: 0xc52b,
+        description: Some("Logitech USB Receiver".to_string()),
+    };
+    let event = usb_event("usb-attached", &device);
+
+    assert_eq!(event.event, "usb-attached");
+    assert_eq!(event.data["vendor_id"], "0x046d");
+    assert_eq!(event.data["product_id"], "0xc52b");
+    assert_eq!(event.data["description"], "Logitech USB Receiver");
+}
@@ -0,0 +1,444 @@
+use crate::config::{GenericRes, OptGenRes};
+use crate::wm_lib;
+use crate::wm_lib::{DesktopLayout, Program};
+use log::{error, info, warn};
+use std::process::{Command, Stdio};
+use std::{thread, time};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, Window};
+use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+
+type XResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// how long to wait for a program's window when the layout doesn't give a delay.
+const WINDOW_TIMEOUT: time::Duration = time::Duration::from_millis(5000);
+/// tells the window manager that a request comes from a pager (or other tool) rather than an
+/// application.
+const SOURCE_PAGER: u32 = 2;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_SUPPORTING_WM_CHECK,
+        _NET_WM_NAME,
+        _NET_CURRENT_DESKTOP,
+        _NET_NUMBER_OF_DESKTOPS,
+        _NET_DESKTOP_NAMES,
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_DESKTOP,
+        _NET_CLOSE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_WM_PID,
+        UTF8_STRING,
+    }
+}
+
+/// a connection to the X server, for window managers that follow the EWMH spec.
+struct Ewmh {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+}
+
+impl Ewmh {
+    fn connect() -> XResult<Ewmh> {
+        let (conn, screen) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let atoms = Atoms::new(&conn)?.reply()?;
+
+        Ok(Ewmh { conn, root, atoms })
+    }
+
+    /// returns a property of `window` as a list of u32s (empty if it's not set).
+    fn get_u32s(&self, window: Window, property: u32) -> XResult<Vec<u32>> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, AtomEnum::ANY, 0, u32::MAX)?
+            .reply()?;
+
+        Ok(reply
+            .value32()
+            .map(|values| values.collect())
+            .unwrap_or_default())
+    }
+
+    fn get_u32(&self, window: Window, property: u32) -> XResult<Option<u32>> {
+        Ok(self.get_u32s(window, property)?.first().copied())
+    }
+
+    /// returns a property of `window` as raw bytes (empty if it's not set).
+    fn get_bytes(&self, window: Window, property: u32) -> XResult<Vec<u8>> {
+        Ok(self
+            .conn
+            .get_property(false, window, property, AtomEnum::ANY, 0, u32::MAX)?
+            .reply()?
+            .value)
+    }
+
+    /// sends a client message about `window` to the root window, which is how EWMH asks the
+    /// window manager to do something.
+    fn request(&self, window: Window, message: u32, data: [u32; 5]) -> XResult<()> {
+        let event = ClientMessageEvent::new(32, window, message, data);
+        self.conn.send_event(
+            false,
+            self.root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// returns the name of the running window manager, if it supports EWMH.
+    fn wm_name(&self) -> XResult<Option<String>> {
+        let Some(check) = self.get_u32(self.root, self.atoms._NET_SUPPORTING_WM_CHECK)? else {
+            return Ok(None);
+        };
+        let name = self.get_bytes(check, self.atoms._NET_WM_NAME)?;
+
+        Ok(Some(String::from_utf8_lossy(&name).to_string()))
+    }
+
+    fn desktop_names(&self) -> XResult<Vec<String>> {
+        let names = self.get_bytes(self.root, self.atoms._NET_DESKTOP_NAMES)?;
+        Ok(split_names(&names))
+    }
+
+    /// returns the index of `desktop`, found by name or else as a number counted from one.
+    fn desktop_index(&self, desktop: &str) -> XResult<Option<u32>> {
+        let count = self
+            .get_u32(self.root, self.atoms._NET_NUMBER_OF_DESKTOPS)?
+            .unwrap_or(0);
+
+        Ok(resolve_desktop(&self.desktop_names()?, count, desktop))
+    }
+
+    fn active_window(&self) -> XResult<Option<Window>> {
+        Ok(self
+            .get_u32(self.root, self.atoms._NET_ACTIVE_WINDOW)?
+            .filter(|window| *window != 0))
+    }
+
+    fn client_list(&self) -> XResult<Vec<Window>> {
+        self.get_u32s(self.root, self.atoms._NET_CLIENT_LIST)
+    }
+
+    /// returns the instance and class of `window`.
+    fn wm_class(&self, window: Window) -> XResult<Vec<String>> {
+        let class = self.get_bytes(window, AtomEnum::WM_CLASS.into())?;
+        Ok(split_names(&class))
+    }
+
+    /// returns true if `window` has the pid `pid` or the wm_class `name`. a window that can't be
+    /// read (eg. a splash screen that's already gone) isn't a match.
+    fn is_window_of(&self, window: Window, pid: u32, name: &str) -> bool {
+        self.get_u32(window, self.atoms._NET_WM_PID)
+            .is_ok_and(|window_pid| window_pid == Some(pid))
+            || self
+                .wm_class(window)
+                .is_ok_and(|classes| classes.iter().any(|class| class.eq_ignore_ascii_case(name)))
+    }
+}
+
+/// splits a list of null terminated strings, like `_NET_DESKTOP_NAMES` or `WM_CLASS`.
+fn split_names(names: &[u8]) -> Vec<String> {
+    names
+        .split(|byte| *byte == 0)
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// returns the index of `desktop` out of `count` desktops. it's looked up in `names` first and
+/// then read as a number counted from one, like the desktops of the other window managers.
+fn resolve_desktop(names: &[String], count: u32, desktop: &str) -> Option<u32> {
+    let desktop = desktop.trim();
+
+    names
+        .iter()
+        .position(|name| name == desktop)
+        .map(|i| i as u32)
+        .or_else(|| desktop.parse::<u32>().ok()?.checked_sub(1))
+        .filter(|i| *i < count)
+}
+
+/// returns true if the running window manager supports EWMH.
+pub fn is_supported() -> bool {
+    match Ewmh::connect().and_then(|ewmh| ewmh.wm_name()) {
+        Ok(Some(name)) => {
+            info!("found an EWMH window manager: \"{name}\"");
+            true
+        }
+        _ => false,
+    }
+}
+
+/// connects to the X server and runs `f`. returns 5 if the X server can't be reached and 6 if
+/// a request fails.
+fn with_ewmh(f: impl FnOnce(&Ewmh) -> XResult<u8>) -> u8 {
+    let ewmh = match Ewmh::connect() {
+        Ok(ewmh) => ewmh,
+        Err(e) => {
+            error!("could not connect to the X server. got error: \"{e}\"");
+            return 5;
+        }
+    };
+
+    f(&ewmh).unwrap_or_else(|e| {
+        error!("an X request failed. got error: \"{e}\"");
+        6
+    })
+}
+
+/// like `Ewmh::desktop_index` but logs desktops that don't exist.
+fn find_desktop(ewmh: &Ewmh, desktop: &str) -> XResult<Option<u32>> {
+    let index = ewmh.desktop_index(desktop)?;
+    if index.is_none() {
+        error!("there is no desktop \"{desktop}\"");
+    }
+    Ok(index)
+}
+
+fn focus_on(desktop: &str) -> u8 {
+    with_ewmh(|ewmh| {
+        let Some(index) = find_desktop(ewmh, desktop)? else {
+            return Ok(4);
+        };
+        ewmh.request(
+            ewmh.root,
+            ewmh.atoms._NET_CURRENT_DESKTOP,
+            [index, CURRENT_TIME, 0, 0, 0],
+        )?;
+        Ok(0)
+    })
+}
+
+fn move_window(ewmh: &Ewmh, window: Window, index: u32) -> XResult<()> {
+    ewmh.request(
+        window,
+        ewmh.atoms._NET_WM_DESKTOP,
+        [index, SOURCE_PAGER, 0, 0, 0],
+    )
+}
+
+fn move_to(desktop: &str) -> u8 {
+    with_ewmh(|ewmh| {
+        let Some(index) = find_desktop(ewmh, desktop)? else {
+            return Ok(4);
+        };
+        let Some(window) = ewmh.active_window()? else {
+            error!("no window is focused");
+            return Ok(4);
+        };
+        move_window(ewmh, window, index)?;
+        Ok(0)
+    })
+}
+
+fn close_focused() -> u8 {
+    with_ewmh(|ewmh| {
+        let Some(window) = ewmh.active_window()? else {
+            error!("no window is focused");
+            return Ok(4);
+        };
+        ewmh.request(
+            window,
+            ewmh.atoms._NET_CLOSE_WINDOW,
+            [CURRENT_TIME, SOURCE_PAGER, 0, 0, 0],
+        )?;
+        Ok(0)
+    })
+}
+
+/// responds with the names of all desktops, one per line.
+fn query_desktops() -> GenericRes {
+    match Ewmh::connect().and_then(|ewmh| ewmh.desktop_names()) {
+        Ok(names) => (0, Some(names.join("\n"))),
+        Err(e) => {
+            error!("could not read the desktop names. got error: \"{e}\"");
+            (5, None)
+        }
+    }
+}
+
+/// runs `program` and moves the first new window with its pid (or its wm_class) to the
+/// desktop `index`.
+fn launch_on(ewmh: &Ewmh, program: &Program, index: u32) -> XResult<u8> {
+    let before = ewmh.client_list()?;
+
+    let mut tokens = vec![program.name.clone()];
+    tokens.extend(program.args.clone().unwrap_or_default());
+    let command = tokens.join(" ");
+    info!("running {command} on desktop {index}");
+
+    // exec keeps the pid of the shell, so it's the pid the window will have.
+    let mut child = match Command::new("sh")
+        .arg("-c")
+        .arg(format!("exec {command}"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            error!("program '{command}' could not be launched: '{e}'");
+            return Ok(4);
+        }
+    };
+    let pid = child.id();
+    thread::spawn(move || child.wait());
+
+    let name = wm_lib::window_name(&program.name, program.wm_class.as_deref());
+    let timeout = match program.delay {
+        Some(times) => time::Duration::from_millis(500 * times as u64),
+        None => WINDOW_TIMEOUT,
+    };
+    let deadline = time::Instant::now() + timeout;
+
+    while time::Instant::now() < deadline {
+        for window in ewmh.client_list()? {
+            if before.contains(&window) {
+                continue;
+            }
+
+            if ewmh.is_window_of(window, pid, &name) {
+                move_window(ewmh, window, index)?;
+                return Ok(0);
+            }
+        }
+
+        thread::sleep(time::Duration::from_millis(100));
+    }
+
+    warn!("no window from {command} showed up, it was left where it opened");
+    Ok(0)
+}
+
+fn set_up_desktop(layout: &DesktopLayout) -> Vec<u8> {
+    wm_lib::launch_programs(&layout.programs, &layout.desktop, |program| {
+        with_ewmh(|ewmh| match find_desktop(ewmh, &layout.desktop)? {
+            Some(index) => launch_on(ewmh, program, index),
+            None => Ok(4),
+        })
+    })
+}
+
+fn load_layout(args: &str) -> u8 {
+    let layout_yaml = match wm_lib::get_layout(args) {
+        Ok(layout) => layout,
+        Err(n) => return n,
+    };
+
+    info!("loading layout {args}");
+
+    if layout_yaml.workspaces.is_some() {
+        warn!("EWMH has no way to put desktops on monitors, the workspaces map is ignored");
+    }
+
+    wm_lib::launch_layouts(layout_yaml.desktops, set_up_desktop)
+}
+
+/// open-at command. `args` is the program (with its arguments) followed by the desktop.
+fn open_on_desktop(args: &str) -> u8 {
+    let Some((program, desktop)) = args.trim().rsplit_once(' ') else {
+        error!("open-at needs a program and a desktop");
+        return 7;
+    };
+
+    let program = Program {
+        name: program.to_string(),
+        state: None,
+        wm_class: None,
+        args: None,
+        delay: None,
+    };
+
+    with_ewmh(|ewmh| match find_desktop(ewmh, desktop)? {
+        Some(index) => launch_on(ewmh, &program, index),
+        None => Ok(4),
+    })
+}
+
+pub async fn ewmh_switch(cmd: &str, args: &str) -> OptGenRes {
+    match cmd {
+        "move-to" => Some((move_to(args), None)),
+        "close-focused" => Some((close_focused(), None)),
+        "open-at" => Some((open_on_desktop(args), None)),
+        "focus-on" => Some((focus_on(args), None)),
+        "load-layout" => Some((load_layout(args), None)),
+        "query-desktops" => Some(query_desktops()),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_resolve_desktop() {
+    let names = split_names(b"web\0chat\0code\0");
+
+    assert_eq!(names, ["web", "chat", "code"]);
+    assert_eq!(resolve_desktop(&names, 4, "chat"), Some(1));
+    assert_eq!(resolve_desktop(&names, 4, "4"), Some(3));
+    assert_eq!(resolve_desktop(&names, 4, "5"), None);
+    assert_eq!(resolve_desktop(&names, 4, "0"), None);
+    assert_eq!(resolve_desktop(&[], 2, "music"), None);
+}
+
+/// needs an X server running an EWMH window manager with at least two desktops, eg.
+/// `xvfb-run sh -c 'openbox & sleep 1 && cargo test -- --ignored test_place_window'`.
+#[test]
+#[ignore]
+fn test_place_window() {
+    use x11rb::protocol::xproto::{CreateWindowAux, PropMode, WindowClass};
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::COPY_DEPTH_FROM_PARENT;
+
+    let ewmh = Ewmh::connect().unwrap();
+    let wait_until = |done: &dyn Fn() -> bool| {
+        let deadline = time::Instant::now() + WINDOW_TIMEOUT;
+        while !done() {
+            assert!(
+                time::Instant::now() < deadline,
+                "the window manager didn't react"
+            );
+            thread::sleep(time::Duration::from_millis(50));
+        }
+    };
+
+    let window = ewmh.conn.generate_id().unwrap();
+    ewmh.conn
+        .create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            ewmh.root,
+            0,
+            0,
+            100,
+            100,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+    ewmh.conn
+        .change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            b"auto-desk-test\0Auto-desk-test\0",
+        )
+        .unwrap();
+    ewmh.conn.map_window(window).unwrap();
+    ewmh.conn.flush().unwrap();
+
+    wait_until(&|| ewmh.client_list().unwrap().contains(&window));
+    assert!(ewmh.is_window_of(window, 0, "auto-desk-test"));
+    move_window(&ewmh, window, 1).unwrap();
+    wait_until(&|| ewmh.get_u32(window, ewmh.atoms._NET_WM_DESKTOP).unwrap() == Some(1));
+
+    ewmh.conn.destroy_window(window).unwrap();
+    ewmh.conn.sync().unwrap();
+    // a window that's gone is skipped rather than ending the wait.
+    assert!(!ewmh.is_window_of(window, 0, "auto-desk-test"));
+}
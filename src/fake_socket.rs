@@ -0,0 +1,82 @@
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// the messages a fake socket got, in the order they came in.
+pub type Received = Arc<Mutex<Vec<String>>>;
+
+/// a socket in the temp dir that stands in for a window manager's ipc socket in tests.
+pub struct FakeSocket {
+    pub path: PathBuf,
+    received: Received,
+}
+
+impl FakeSocket {
+    /// listens on a fresh socket for the test `name` and hands every connection to `serve` on
+    /// another thread, along with the log to record its messages in.
+    pub fn new(name: &str, serve: impl FnMut(UnixStream, &Received) + Send + 'static) -> Self {
+        let path = std::env::temp_dir().join(format!("auto-desk-{name}-{}", std::process::id()));
+        FakeSocket::at(path, serve)
+    }
+
+    /// like `new`, for a socket that has to be at `path`.
+    pub fn at(
+        path: PathBuf,
+        mut serve: impl FnMut(UnixStream, &Received) + Send + 'static,
+    ) -> Self {
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let received = Received::default();
+
+        let tmp_received = received.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                serve(stream, &tmp_received);
+            }
+        });
+
+        FakeSocket { path, received }
+    }
+
+    /// like `new`, for protocols that send one message per connection. `read` reads the message
+    /// and it's answered with what `reply` returns for it.
+    pub fn answering(
+        name: &str,
+        read: fn(&mut UnixStream) -> String,
+        mut reply: impl FnMut(&str) -> Vec<u8> + Send + 'static,
+    ) -> Self {
+        FakeSocket::new(name, move |mut stream, received| {
+            let message = read(&mut stream);
+            let answer = reply(&message);
+            // logged before it's answered, so the client sees it in the log once it's answered.
+            received.lock().unwrap().push(message);
+            let _ = stream.write_all(&answer);
+        })
+    }
+
+    /// returns the messages it got so far.
+    pub fn received(&self) -> Vec<String> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// stops clients from connecting, as if the window manager quit.
+    pub fn remove(&self) {
+        std::fs::remove_file(&self.path).unwrap();
+    }
+}
+
+/// reads a message that ends when the client shuts down its side.
+pub fn read_to_end(stream: &mut UnixStream) -> String {
+    let mut message = String::new();
+    let _ = stream.read_to_string(&mut message);
+    message
+}
+
+/// reads whatever a single write of the client holds.
+pub fn read_once(stream: &mut UnixStream) -> String {
+    let mut message = [0; 1024];
+    let len = stream.read(&mut message).unwrap_or_default();
+    String::from_utf8_lossy(&message[..len]).to_string()
+}
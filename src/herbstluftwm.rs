@@ -0,0 +1,320 @@
+use crate::common::open_program;
+use crate::config::{GenericRes, OptGenRes};
+use crate::wm_lib;
+use crate::wm_lib::{DesktopLayout, Program};
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{thread, time};
+
+/// how long to wait for a program's window when the layout doesn't give a delay.
+const WINDOW_TIMEOUT: time::Duration = time::Duration::from_millis(5000);
+
+/// numbers the labels of the rules added for each launched program.
+static RULE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// runs `herbstclient` with `args` and returns what it printed. returns 5 if herbstclient can't
+/// be run and 6 if herbstluftwm reports an error.
+fn hc(args: &[&str]) -> Result<String, u8> {
+    let output = match Command::new("herbstclient").args(args).output() {
+        Ok(output) => output,
+        Err(e) => {
+            error!("could not run herbstclient. got error: \"{e}\"");
+            return Err(5);
+        }
+    };
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        error!(
+            "herbstluftwm returned error: \"{}\" (for {args:?})",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        Err(6)
+    }
+}
+
+/// like `hc` but only returns the error code.
+fn send(args: &[&str]) -> u8 {
+    hc(args).err().unwrap_or(0)
+}
+
+fn focus_on(desktop: &str) -> u8 {
+    send(&["use", desktop.trim()])
+}
+
+fn move_to(desktop: &str) -> u8 {
+    send(&["move", desktop.trim()])
+}
+
+fn close_focused() -> u8 {
+    send(&["close"])
+}
+
+/// returns the tag names in the output of `herbstclient tag_status`, where each tag is
+/// prefixed by a character describing its state. (eg. "\t#1\t:2\t.3\t")
+fn parse_tag_status(status: &str) -> Vec<&str> {
+    status
+        .split('\t')
+        .filter_map(|tag| tag.get(1..))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// responds with the names of all desktops (tags), one per line.
+fn query_desktops() -> GenericRes {
+    match hc(&["tag_status"]) {
+        Ok(status) => (0, Some(parse_tag_status(&status).join("\n"))),
+        Err(ec) => (ec, None),
+    }
+}
+
+/// returns the one-shot rules that send a window of `exec` to `desktop`. they all get `label` so
+/// the ones that aren't used can be removed.
+fn make_rules(exec: &str, desktop: &str, label: &str) -> Vec<Vec<String>> {
+    let mut chars = exec.chars();
+    let capitalized: String = chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default();
+    let mut conditions = vec![
+        format!("class={exec}"),
+        format!("class={capitalized}"),
+        format!("instance={exec}"),
+    ];
+    conditions.dedup();
+
+    conditions
+        .into_iter()
+        .map(|condition| {
+            [
+                "rule",
+                "once",
+                &format!("label={label}"),
+                &condition,
+                &format!("tag={desktop}"),
+                "focus=off",
+                "switchtag=off",
+            ]
+            .map(String::from)
+            .to_vec()
+        })
+        .collect()
+}
+
+/// counts the rules labeled `label` in the output of `herbstclient list_rules`, which has a rule
+/// per line with its properties separated by tabs.
+fn count_rules(listing: &str, label: &str) -> usize {
+    let label = format!("label={label}");
+
+    listing
+        .lines()
+        .filter(|rule| rule.split('\t').any(|property| property == label))
+        .count()
+}
+
+/// waits until one of the `count` rules labeled `label` is used up by a window, or `deadline`
+/// passes. returns true if one was.
+fn wait_for_rule(label: &str, count: usize, deadline: time::Instant) -> bool {
+    while time::Instant::now() < deadline {
+        match hc(&["list_rules"]) {
+            Ok(listing) if count_rules(&listing, label) < count => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+
+        thread::sleep(time::Duration::from_millis(100));
+    }
+
+    false
+}
+
+/// launches `program` with one-shot rules that send its window to `desktop`, waits for its
+/// window to use one of them (up to `delay` * 500ms, or `WINDOW_TIMEOUT`), then removes the
+/// rules that weren't used.
+fn run_exec(program: &Program, desktop: &str) -> u8 {
+    let exec = wm_lib::window_name(&program.name, program.wm_class.as_deref());
+    if exec.is_empty() {
+        error!("can't make rules for a program without a name");
+        return 7;
+    }
+
+    let label = format!(
+        "auto-desk-{}-{}",
+        std::process::id(),
+        RULE_COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let rules = make_rules(&exec, desktop, &label);
+    for rule in &rules {
+        let rule: Vec<&str> = rule.iter().map(String::as_str).collect();
+        if send(&rule) > 0 {
+            return 3;
+        }
+    }
+
+    let mut tokens = vec![program.name.clone()];
+    tokens.extend(program.args.clone().unwrap_or_default());
+    let error_code = open_program(&tokens.join(" "));
+
+    let timeout = match program.delay {
+        Some(times) => time::Duration::from_millis(500 * times as u64),
+        None => WINDOW_TIMEOUT,
+    };
+
+    if error_code == 0 && !wait_for_rule(&label, rules.len(), time::Instant::now() + timeout) {
+        warn!(
+            "no window from {} showed up, it was left where it opened",
+            program.name
+        );
+    }
+
+    // unrule fails when every rule with the label has been used, which is fine.
+    let _ = Command::new("herbstclient")
+        .args(["unrule", &label])
+        .output();
+
+    error_code
+}
+
+/// open-at command. `args` is the program (with its arguments) followed by the desktop.
+fn open_on_desktop(args: &str) -> u8 {
+    let Some((program, desktop)) = args.trim().rsplit_once(' ') else {
+        error!("open-at needs a program and a desktop");
+        return 7;
+    };
+
+    info!("running {program} on desktop {desktop}");
+    let (name, args) = match program.split_once(' ') {
+        Some((name, args)) => (name, Some(vec![args.to_string()])),
+        None => (program, None),
+    };
+    let program = Program {
+        name: name.to_string(),
+        state: None,
+        wm_class: None,
+        args,
+        delay: None,
+    };
+
+    run_exec(&program, desktop)
+}
+
+/// closes every window on `desktop`.
+fn clear_desktop(desktop: &str) -> u8 {
+    let clients = match hc(&["list_clients", &format!("--tag={desktop}")]) {
+        Ok(clients) => clients,
+        Err(ec) => return ec,
+    };
+
+    for client in clients.split_whitespace() {
+        let ec = send(&["close", client]);
+        if ec > 0 {
+            return ec;
+        }
+    }
+
+    0
+}
+
+fn load_layout(args: &str) -> u8 {
+    let layout_yaml = match wm_lib::get_layout(args) {
+        Ok(layout) => layout,
+        Err(n) => return n,
+    };
+
+    info!("loading layout {args}");
+
+    let error_code = wm_lib::launch_layouts(layout_yaml.desktops, set_up_desktop);
+
+    if error_code > 0 {
+        return error_code;
+    }
+
+    match layout_yaml.workspaces {
+        Some(workspaces) => set_workspaces(&workspaces),
+        None => 0,
+    }
+}
+
+fn set_up_desktop(layout: &DesktopLayout) -> Vec<u8> {
+    if layout.clear.unwrap_or(false) {
+        let ec = clear_desktop(&layout.desktop);
+        if ec > 0 {
+            error!("could not clear desktop {}", layout.desktop);
+            return vec![ec];
+        }
+    }
+
+    wm_lib::launch_programs(&layout.programs, &layout.desktop, |program| {
+        run_exec(program, &layout.desktop)
+    })
+}
+
+/// shows desktops on the monitors (workspaces) described by the layout's `workspaces` map.
+/// both monitors and desktops are counted from zero.
+fn set_workspaces(workspaces: &HashMap<i32, i32>) -> u8 {
+    for (workspace, desktop) in workspaces {
+        let ec = send(&[
+            "chain",
+            ",",
+            "focus_monitor",
+            &workspace.to_string(),
+            ",",
+            "use_index",
+            &desktop.to_string(),
+        ]);
+        if ec > 0 {
+            error!("could not show desktop {desktop} on monitor {workspace}");
+            return ec;
+        }
+    }
+
+    0
+}
+
+fn apply_workspaces(args: &str) -> u8 {
+    match wm_lib::get_workspaces(args) {
+        Ok(workspaces) => set_workspaces(&workspaces),
+        Err(n) => n,
+    }
+}
+
+pub async fn herbstluftwm_switch(cmd: &str, args: &str) -> OptGenRes {
+    match cmd {
+        "move-to" => Some((move_to(args), None)),
+        "close-focused" => Some((close_focused(), None)),
+        "open-at" => Some((open_on_desktop(args), None)),
+        "focus-on" => Some((focus_on(args), None)),
+        "load-layout" => Some((load_layout(args), None)),
+        "apply-workspaces" => Some((apply_workspaces(args), None)),
+        "query-desktops" => Some(query_desktops()),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_make_rules() {
+    let rules = make_rules("kitty", "3", "auto-desk-1-0");
+
+    assert_eq!(rules.len(), 3);
+    assert_eq!(
+        rules[1],
+        [
+            "rule",
+            "once",
+            "label=auto-desk-1-0",
+            "class=Kitty",
+            "tag=3",
+            "focus=off",
+            "switchtag=off"
+        ]
+    );
+    assert_eq!(parse_tag_status("\t#1\t:2\t.web\t"), ["1", "2", "web"]);
+
+    let listing = "label=auto-desk-1-0\tclass=Kitty\ttag=3\tonce\n\
+                   label=auto-desk-1-01\tclass=kitty\ttag=2\tonce\n\
+                   label=auto-desk-1-0\tinstance=kitty\ttag=3\tonce\n";
+    assert_eq!(count_rules(listing, "auto-desk-1-0"), 2);
+}
@@ -0,0 +1,121 @@
+use crate::config::Hook;
+use crate::events::{Event, EventRx};
+use log::{debug, error, info, warn};
+use std::process::Stdio;
+use tokio::process::Command;
+use tokio::sync::broadcast::error::RecvError;
+
+/// parses a number the way event data stores them, either as decimal or as "0x" prefixed hex.
+fn parse_number(value: &str) -> Option<i64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// returns true if the event data, `value`, matches the value from a hooks `match` table.
+fn value_matches(wanted: &toml::Value, value: &str) -> bool {
+    match wanted {
+        toml::Value::Integer(i) => parse_number(value) == Some(*i),
+        toml::Value::String(s) => {
+            s == value
+                || matches!((parse_number(s), parse_number(value)), (Some(a), Some(b)) if a == b)
+        }
+        toml::Value::Boolean(b) => value == b.to_string(),
+        _ => false,
+    }
+}
+
+/// returns true if `hook` should be run for `event`.
+fn hook_matches(hook: &Hook, event: &Event) -> bool {
+    hook.event == event.event
+        && hook
+            .filter
+            .iter()
+            .all(|(key, wanted)| match event.data.get(key) {
+                Some(value) => value_matches(wanted, value),
+                None => false,
+            })
+}
+
+/// turns an event data key into an environment variable name. (eg. "output" => "AUTO_DESK_OUTPUT")
+fn env_name(key: &str) -> String {
+    format!("AUTO_DESK_{}", key.replace('-', "_").to_uppercase())
+}
+
+/// runs a single hook as a shell command. the event data is passed to the hook via
+/// environment variables prefixed with "AUTO_DESK_".
+fn run_hook(hook: &Hook, event: &Event) {
+    let exec = shellexpand::tilde(&hook.exec).to_string();
+    info!("running hook \"{exec}\" for event {}", event.event);
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(&exec)
+        .env("AUTO_DESK_EVENT", &event.event)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    for (key, value) in &event.data {
+        cmd.env(env_name(key), value);
+    }
+
+    if let Err(e) = cmd.spawn() {
+        error!("hook \"{exec}\" could not be run. got error: \"{e}\"");
+    }
+}
+
+/// listens for events and runs every configured hook whose event matches.
+pub async fn check_event_hooks(events: &mut EventRx, hooks: Vec<Hook>) {
+    info!("listening for events to run hooks on");
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(n)) => {
+                warn!("hook listener fell behind, {n} events were not checked for hooks");
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        debug!("checking hooks for event: {event:?}");
+
+        for hook in hooks.iter().filter(|hook| hook_matches(hook, &event)) {
+            run_hook(hook, &event);
+        }
+    }
+
+    info!("event bus closed, no longer running hooks");
+}
+
+#[test]
+fn test_env_name() {
+    assert_eq!(env_name("output"), "AUTO_DESK_OUTPUT");
+    assert_eq!(env_name("vendor-id"), "AUTO_DESK_VENDOR_ID");
+}
+
+#[test]
+fn test_hook_matches() {
+    let hook: Hook = toml::from_str(
+        r#"
+        event = "usb-attached"
+        exec = "true"
+        match = { vendor_id = 0x046d, description = "Keyboard" }
+        "#,
+    )
+    .unwrap();
+    let event = Event::new("usb-attached")
+        .with("vendor_id", "0x046d")
+        .with("product_id", "0xc52b")
+        .with("description", "Keyboard");
+
+    assert!(hook_matches(&hook, &event));
+    assert!(!hook_matches(
+        &hook,
+        &event.clone().with("vendor_id", "0x1234")
+    ));
+    assert!(!hook_matches(
+        &hook,
+        &Event::new("usb-detached").with("vendor_id", "0x046d")
+    ));
+}
@@ -0,0 +1,282 @@
+use crate::config::{GenericRes, OptGenRes};
+use crate::wm_lib;
+use crate::wm_lib::{DesktopLayout, Program};
+use log::{error, info};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::{thread, time};
+
+#[cfg(test)]
+use crate::fake_socket::{read_once, FakeSocket};
+
+/// the socket that takes dispatchers and queries.
+pub const COMMAND_SOCKET: &str = ".socket.sock";
+/// the socket that streams events.
+pub const EVENT_SOCKET: &str = ".socket2.sock";
+
+/// returns the dir of the running Hyprland instance's sockets,
+/// "$XDG_RUNTIME_DIR/hypr/$HYPRLAND_INSTANCE_SIGNATURE" (or "/tmp/hypr/<sig>" for older
+/// versions).
+pub fn socket_dir() -> Option<PathBuf> {
+    let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(&dir).join("hypr").join(&signature));
+
+    runtime_dir
+        .into_iter()
+        .chain([Path::new("/tmp/hypr").join(&signature)])
+        .find(|dir| dir.join(COMMAND_SOCKET).exists())
+}
+
+/// sends a request (eg. "dispatch workspace 3" or "j/workspaces") to `socket` and returns the
+/// reply. returns 5 if the socket can't be used.
+fn request(socket: &Path, message: &str) -> Result<String, u8> {
+    let mut stream = UnixStream::connect(socket).map_err(|e| {
+        error!("could not connect to Hyprland at {socket:?}. got error: \"{e}\"");
+        5
+    })?;
+
+    let mut reply = String::new();
+    stream
+        .write_all(message.as_bytes())
+        .and_then(|_| stream.read_to_string(&mut reply))
+        .map_err(|e| {
+            error!("could not talk to Hyprland. got error: \"{e}\"");
+            5
+        })?;
+
+    Ok(reply)
+}
+
+/// runs a dispatcher. returns 6 if Hyprland doesn't answer "ok".
+fn dispatch(socket: &Path, dispatcher: &str) -> u8 {
+    match request(socket, &format!("dispatch {dispatcher}")) {
+        Ok(reply) if reply.trim() == "ok" => 0,
+        Ok(reply) => {
+            error!("Hyprland could not run \"{dispatcher}\": {}", reply.trim());
+            6
+        }
+        Err(ec) => ec,
+    }
+}
+
+/// turns a desktop into Hyprland's workspace syntax, numbers are workspace ids and anything
+/// else is a workspace name.
+fn workspace(desktop: &str) -> String {
+    let desktop = desktop.trim();
+    match desktop.parse::<i32>() {
+        Ok(id) => id.to_string(),
+        Err(_) => format!("name:{desktop}"),
+    }
+}
+
+/// the exec dispatcher that runs `program` on `desktop` without switching to it.
+fn exec_on(program: &str, desktop: &str) -> String {
+    format!("exec [workspace {} silent] {program}", workspace(desktop))
+}
+
+fn focus_on(socket: &Path, desktop: &str) -> u8 {
+    dispatch(socket, &format!("workspace {}", workspace(desktop)))
+}
+
+fn move_to(socket: &Path, desktop: &str) -> u8 {
+    dispatch(
+        socket,
+        &format!("movetoworkspacesilent {}", workspace(desktop)),
+    )
+}
+
+fn close_focused(socket: &Path) -> u8 {
+    dispatch(socket, "killactive")
+}
+
+/// open-at command. `args` is the program (with its arguments) followed by the desktop.
+fn open_on_desktop(socket: &Path, args: &str) -> u8 {
+    let Some((program, desktop)) = args.trim().rsplit_once(' ') else {
+        error!("open-at needs a program and a desktop");
+        return 7;
+    };
+
+    info!("running {program} on desktop {desktop}");
+    dispatch(socket, &exec_on(program, desktop))
+}
+
+/// responds with the names of all desktops (workspaces), one per line.
+fn query_desktops(socket: &Path) -> GenericRes {
+    let reply = match request(socket, "j/workspaces") {
+        Ok(reply) => reply,
+        Err(ec) => return (ec, None),
+    };
+
+    match serde_json::from_str::<Value>(&reply) {
+        Ok(workspaces) => {
+            let names: Vec<&str> = workspaces
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|workspace| workspace["name"].as_str())
+                .collect();
+            (0, Some(names.join("\n")))
+        }
+        Err(e) => {
+            error!("could not read Hyprland's workspaces. got error: \"{e}\"");
+            (6, None)
+        }
+    }
+}
+
+fn load_layout(socket: &Path, args: &str) -> u8 {
+    let layout_yaml = match wm_lib::get_layout(args) {
+        Ok(layout) => layout,
+        Err(n) => return n,
+    };
+
+    info!("loading layout {args}");
+
+    let tmp_socket = socket.to_path_buf();
+    let error_code = wm_lib::launch_layouts(layout_yaml.desktops, move |layout| {
+        set_up_desktop(&tmp_socket, layout)
+    });
+
+    if error_code > 0 {
+        return error_code;
+    }
+
+    match layout_yaml.workspaces {
+        Some(workspaces) => set_workspaces(socket, &workspaces),
+        None => 0,
+    }
+}
+
+/// launches every program of the desktop, each with its own workspace rule so no window has to
+/// be found after it opens.
+fn set_up_desktop(socket: &Path, layout: &DesktopLayout) -> Vec<u8> {
+    wm_lib::launch_programs(&layout.programs, &layout.desktop, |program| {
+        run_exec(socket, program, &layout.desktop)
+    })
+}
+
+fn run_exec(socket: &Path, program: &Program, desktop: &str) -> u8 {
+    let mut tokens = vec![program.name.clone()];
+    tokens.extend(program.args.clone().unwrap_or_default());
+
+    let ec = dispatch(socket, &exec_on(&tokens.join(" "), desktop));
+
+    // gives the program time to open before the next one, so they open in order.
+    let t = match program.delay {
+        Some(times) => time::Duration::from_millis(500 * times as u64),
+        None => time::Duration::from_millis(500),
+    };
+    if ec == 0 {
+        thread::sleep(t);
+    }
+
+    ec
+}
+
+/// moves desktops to the monitors (workspaces) described by the layout's `workspaces` map.
+/// monitors are counted from zero in the order Hyprland lists them, desktops are counted from
+/// zero too.
+fn set_workspaces(socket: &Path, workspaces: &HashMap<i32, i32>) -> u8 {
+    let monitors: Vec<String> =
+        match request(socket, "j/monitors").map(|reply| serde_json::from_str::<Value>(&reply)) {
+            Ok(Ok(monitors)) => monitors
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|monitor| monitor["name"].as_str().map(String::from))
+                .collect(),
+            Ok(Err(e)) => {
+                error!("could not read Hyprland's monitors. got error: \"{e}\"");
+                return 6;
+            }
+            Err(ec) => return ec,
+        };
+
+    for (monitor, desktop) in workspaces {
+        let Some(name) = usize::try_from(*monitor).ok().and_then(|i| monitors.get(i)) else {
+            error!(
+                "there is no monitor {monitor}, only {} exist",
+                monitors.len()
+            );
+            return 6;
+        };
+
+        let ec = dispatch(
+            socket,
+            &format!("moveworkspacetomonitor {} {name}", desktop + 1),
+        );
+        if ec > 0 {
+            error!("could not move desktop {desktop} to monitor {name}");
+            return ec;
+        }
+    }
+
+    0
+}
+
+fn apply_workspaces(socket: &Path, args: &str) -> u8 {
+    match wm_lib::get_workspaces(args) {
+        Ok(workspaces) => set_workspaces(socket, &workspaces),
+        Err(n) => n,
+    }
+}
+
+pub async fn hyprland_switch(cmd: &str, args: &str) -> OptGenRes {
+    if !matches!(
+        cmd,
+        "move-to"
+            | "close-focused"
+            | "open-at"
+            | "focus-on"
+            | "load-layout"
+            | "apply-workspaces"
+            | "query-desktops"
+    ) {
+        return None;
+    }
+
+    let Some(socket) = socket_dir().map(|dir| dir.join(COMMAND_SOCKET)) else {
+        error!("could not find Hyprland's socket, is HYPRLAND_INSTANCE_SIGNATURE set?");
+        return Some((5, Some("could not find Hyprland's socket".to_string())));
+    };
+
+    match cmd {
+        "move-to" => Some((move_to(&socket, args), None)),
+        "close-focused" => Some((close_focused(&socket), None)),
+        "open-at" => Some((open_on_desktop(&socket, args), None)),
+        "focus-on" => Some((focus_on(&socket, args), None)),
+        "load-layout" => Some((load_layout(&socket, args), None)),
+        "apply-workspaces" => Some((apply_workspaces(&socket, args), None)),
+        "query-desktops" => Some(query_desktops(&socket)),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_dispatch() {
+    let mut replies = ["ok", "Invalid dispatcher"].into_iter();
+    let fake = FakeSocket::answering("hypr", read_once, move |_| {
+        replies.next().unwrap_or_default().into()
+    });
+
+    assert_eq!(
+        open_on_desktop(&fake.path, "kitty --single-instance web"),
+        0
+    );
+    assert_eq!(close_focused(&fake.path), 6);
+    assert_eq!(
+        fake.received(),
+        [
+            "dispatch exec [workspace name:web silent] kitty --single-instance",
+            "dispatch killactive"
+        ]
+    );
+    fake.remove();
+    assert_eq!(focus_on(&fake.path, "2"), 5);
+}
@@ -0,0 +1,468 @@
+use crate::common::open_program;
+use crate::config::OptGenRes;
+use crate::wm_lib;
+use crate::wm_lib::{DesktopLayout, Program};
+use log::{error, info, warn};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+use crate::fake_socket::FakeSocket;
+
+const MAGIC: &[u8] = b"i3-ipc";
+const HEADER_LEN: usize = 14;
+
+const RUN_COMMAND: u32 = 0;
+const SUBSCRIBE: u32 = 2;
+const GET_OUTPUTS: u32 = 3;
+/// events have the highest bit of their type set.
+const EVENT_WINDOW: u32 = 0x8000_0003;
+
+/// how long to wait for a program's window when the layout doesn't give a delay.
+const WINDOW_TIMEOUT: Duration = Duration::from_millis(5000);
+/// how long to wait for the window manager to reply to a message.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(2000);
+
+const I3_COMMANDS: [&str; 6] = [
+    "move-to",
+    "close-focused",
+    "open-at",
+    "focus-on",
+    "load-layout",
+    "apply-workspaces",
+];
+
+/// a connection to the ipc socket of i3 or sway. both speak the same protocol, messages are
+/// "i3-ipc", the payload length, and the message type (both native endian u32s), followed by
+/// the payload.
+struct Ipc {
+    stream: UnixStream,
+}
+
+impl Ipc {
+    fn connect(socket: &Path) -> std::io::Result<Ipc> {
+        let stream = UnixStream::connect(socket)?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        Ok(Ipc { stream })
+    }
+
+    fn send(&mut self, msg_type: u32, payload: &str) -> std::io::Result<()> {
+        self.stream.write_all(&encode(msg_type, payload))
+    }
+
+    fn read(&mut self) -> std::io::Result<(u32, Value)> {
+        let mut header = [0; HEADER_LEN];
+        self.stream.read_exact(&mut header)?;
+        let (len, msg_type) = decode_header(&header)?;
+
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload)?;
+        let value = serde_json::from_slice(&payload)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        Ok((msg_type, value))
+    }
+
+    /// reads the next message, or returns None once `deadline` passes.
+    fn read_until(&mut self, deadline: Instant) -> Option<std::io::Result<(u32, Value)>> {
+        let left = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|left| !left.is_zero())?;
+
+        match self
+            .stream
+            .set_read_timeout(Some(left))
+            .and_then(|_| self.read())
+        {
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => None,
+            read => Some(read),
+        }
+    }
+
+    /// sends a message and returns the reply, skipping any events that come before it.
+    fn request(&mut self, msg_type: u32, payload: &str) -> std::io::Result<Value> {
+        self.send(msg_type, payload)?;
+
+        loop {
+            let (reply_type, reply) = self.read()?;
+            if reply_type == msg_type {
+                return Ok(reply);
+            }
+        }
+    }
+}
+
+fn encode(msg_type: u32, payload: &str) -> Vec<u8> {
+    let mut message = MAGIC.to_vec();
+    message.extend((payload.len() as u32).to_ne_bytes());
+    message.extend(msg_type.to_ne_bytes());
+    message.extend(payload.as_bytes());
+    message
+}
+
+/// returns the payload length and the message type of a message header.
+fn decode_header(header: &[u8; HEADER_LEN]) -> std::io::Result<(usize, u32)> {
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the reply doesn't start with \"i3-ipc\"",
+        ));
+    }
+
+    let len = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+    let msg_type = u32::from_ne_bytes([header[10], header[11], header[12], header[13]]);
+    Ok((len as usize, msg_type))
+}
+
+/// returns the ipc socket of `wm` ("i3" or "sway"), from $I3SOCK/$SWAYSOCK or by asking the
+/// window manager.
+fn get_socket(wm: &str) -> Option<PathBuf> {
+    let var = if wm == "sway" { "SWAYSOCK" } else { "I3SOCK" };
+    if let Ok(socket) = std::env::var(var) {
+        return Some(PathBuf::from(socket));
+    }
+
+    let output = Command::new(wm).arg("--get-socketpath").output().ok()?;
+    let socket = String::from_utf8(output.stdout).ok()?;
+    let socket = socket.trim();
+    (output.status.success() && !socket.is_empty()).then(|| PathBuf::from(socket))
+}
+
+/// runs an i3 command (eg. "workspace number 3"). returns 5 if the socket can't be used and 6
+/// if the window manager says the command failed.
+fn run_command(socket: &Path, command: &str) -> u8 {
+    let reply = match Ipc::connect(socket).and_then(|mut ipc| ipc.request(RUN_COMMAND, command)) {
+        Ok(reply) => reply,
+        Err(e) => {
+            error!("could not talk to the window manager over {socket:?}. got error: \"{e}\"");
+            return 5;
+        }
+    };
+
+    let failures: Vec<&Value> = reply
+        .as_array()
+        .map(|results| {
+            results
+                .iter()
+                .filter(|result| result["success"] != true)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if failures.is_empty() {
+        0
+    } else {
+        for failure in failures {
+            error!(
+                "the window manager could not run \"{command}\": {}",
+                failure["error"].as_str().unwrap_or("unknown error")
+            );
+        }
+        6
+    }
+}
+
+/// turns a desktop into i3's workspace syntax, numbers are used as workspace numbers and
+/// anything else as a workspace name.
+fn workspace(desktop: &str) -> String {
+    match desktop.trim().parse::<u32>() {
+        Ok(number) => format!("number {number}"),
+        Err(_) => format!("\"{}\"", desktop.trim().replace('"', "\\\"")),
+    }
+}
+
+fn focus_on(socket: &Path, desktop: &str) -> u8 {
+    run_command(socket, &format!("workspace {}", workspace(desktop)))
+}
+
+fn move_to(socket: &Path, desktop: &str) -> u8 {
+    run_command(
+        socket,
+        &format!("move container to workspace {}", workspace(desktop)),
+    )
+}
+
+fn close_focused(socket: &Path) -> u8 {
+    run_command(socket, "kill")
+}
+
+/// returns true if the window of `container` belongs to `name`, ie. its app_id (sway), class,
+/// or instance is `name` (ignoring case).
+fn is_program_window(container: &Value, name: &str) -> bool {
+    [
+        &container["app_id"],
+        &container["window_properties"]["class"],
+        &container["window_properties"]["instance"],
+    ]
+    .iter()
+    .filter_map(|value| value.as_str())
+    .any(|value| value.eq_ignore_ascii_case(name))
+}
+
+/// launches `program` and moves its first new window to `desktop`. i3 can't add `for_window`
+/// rules at runtime, so this watches for the window instead, which works like a rule that's
+/// removed once it's used (or after `timeout`).
+fn open_on_desktop(
+    socket: &Path,
+    program: &str,
+    name: &str,
+    desktop: &str,
+    timeout: Duration,
+) -> u8 {
+    let mut events = match Ipc::connect(socket).and_then(|mut ipc| {
+        ipc.request(SUBSCRIBE, "[\"window\"]")?;
+        Ok(ipc)
+    }) {
+        Ok(events) => events,
+        Err(e) => {
+            error!("could not subscribe to window events over {socket:?}. got error: \"{e}\"");
+            return 5;
+        }
+    };
+
+    info!("running {program} on desktop {desktop}");
+    let ec = open_program(program);
+    if ec > 0 {
+        return ec;
+    }
+
+    let deadline = Instant::now() + timeout;
+
+    while let Some(read) = events.read_until(deadline) {
+        let (msg_type, event) = match read {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("stopped waiting for a window from {program}. reason: \"{e}\"");
+                break;
+            }
+        };
+
+        if msg_type == EVENT_WINDOW
+            && event["change"] == "new"
+            && is_program_window(&event["container"], name)
+        {
+            return run_command(
+                socket,
+                &format!(
+                    "[con_id={}] move container to workspace {}",
+                    event["container"]["id"],
+                    workspace(desktop)
+                ),
+            );
+        }
+    }
+
+    warn!("no window from {program} showed up, it was left where it opened");
+    0
+}
+
+/// open-at command. `args` is the program (with its arguments) followed by the desktop.
+fn open_at(socket: &Path, args: &str) -> u8 {
+    let Some((program, desktop)) = args.trim().rsplit_once(' ') else {
+        error!("open-at needs a program and a desktop");
+        return 7;
+    };
+
+    open_on_desktop(
+        socket,
+        program,
+        &wm_lib::window_name(program, None),
+        desktop,
+        WINDOW_TIMEOUT,
+    )
+}
+
+fn load_layout(socket: &Path, args: &str) -> u8 {
+    let layout_yaml = match wm_lib::get_layout(args) {
+        Ok(layout) => layout,
+        Err(n) => return n,
+    };
+
+    info!("loading layout {args}");
+
+    let tmp_socket = socket.to_path_buf();
+    let error_code = wm_lib::launch_layouts(layout_yaml.desktops, move |layout| {
+        set_up_desktop(&tmp_socket, layout)
+    });
+
+    if error_code > 0 {
+        return error_code;
+    }
+
+    match layout_yaml.workspaces {
+        Some(workspaces) => set_workspaces(socket, &workspaces),
+        None => 0,
+    }
+}
+
+fn set_up_desktop(socket: &Path, layout: &DesktopLayout) -> Vec<u8> {
+    wm_lib::launch_programs(&layout.programs, &layout.desktop, |program| {
+        open_program_at(socket, program, &layout.desktop)
+    })
+}
+
+fn open_program_at(socket: &Path, program: &Program, desktop: &str) -> u8 {
+    let mut tokens = vec![program.name.clone()];
+    tokens.extend(program.args.clone().unwrap_or_default());
+    let timeout = match program.delay {
+        Some(times) => Duration::from_millis(500 * times as u64),
+        None => WINDOW_TIMEOUT,
+    };
+
+    open_on_desktop(
+        socket,
+        &tokens.join(" "),
+        &wm_lib::window_name(&program.name, program.wm_class.as_deref()),
+        desktop,
+        timeout,
+    )
+}
+
+/// moves desktops to the outputs (workspaces) described by the layout's `workspaces` map.
+/// outputs are counted from zero in the order the window manager lists them, desktops are
+/// counted from zero too.
+fn set_workspaces(socket: &Path, workspaces: &HashMap<i32, i32>) -> u8 {
+    let outputs = match Ipc::connect(socket).and_then(|mut ipc| ipc.request(GET_OUTPUTS, "")) {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            error!("could not get the outputs over {socket:?}. got error: \"{e}\"");
+            return 5;
+        }
+    };
+    let outputs: Vec<&str> = outputs
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|output| output["active"] == true)
+        .filter_map(|output| output["name"].as_str())
+        .collect();
+
+    for (output, desktop) in workspaces {
+        let Some(name) = usize::try_from(*output).ok().and_then(|i| outputs.get(i)) else {
+            error!(
+                "there is no output {output}, only {} are active",
+                outputs.len()
+            );
+            return 6;
+        };
+
+        // `workspace <ws> output <output>` only works in the config file on i3.
+        let ec = run_command(
+            socket,
+            &format!(
+                "workspace {}; move workspace to output {name}",
+                workspace(&(desktop + 1).to_string())
+            ),
+        );
+        if ec > 0 {
+            error!("could not move desktop {desktop} to output {name}");
+            return ec;
+        }
+    }
+
+    0
+}
+
+fn apply_workspaces(socket: &Path, args: &str) -> u8 {
+    match wm_lib::get_workspaces(args) {
+        Ok(workspaces) => set_workspaces(socket, &workspaces),
+        Err(n) => n,
+    }
+}
+
+/// handles the commands of i3 and sway. `wm` is the one running, "i3" or "sway".
+pub async fn i3_switch(cmd: &str, args: &str, wm: &str) -> OptGenRes {
+    if !I3_COMMANDS.contains(&cmd) {
+        return None;
+    }
+
+    let Some(socket) = get_socket(wm) else {
+        error!("could not find the ipc socket of {wm}");
+        return Some((5, Some(format!("could not find the ipc socket of {wm}"))));
+    };
+
+    match cmd {
+        "move-to" => Some((move_to(&socket, args), None)),
+        "close-focused" => Some((close_focused(&socket), None)),
+        "open-at" => Some((open_at(&socket, args), None)),
+        "focus-on" => Some((focus_on(&socket, args), None)),
+        "load-layout" => Some((load_layout(&socket, args), None)),
+        "apply-workspaces" => Some((apply_workspaces(&socket, args), None)),
+        _ => None,
+    }
+}
+
+/// starts a fake i3 that answers each message with the next of `replies` (a message type and
+/// payload). the payloads of the messages it gets are recorded.
+#[cfg(test)]
+fn fake_ipc(name: &str, replies: Vec<(u32, &'static str)>) -> FakeSocket {
+    let mut replies = replies.into_iter();
+
+    FakeSocket::new(&format!("i3-{name}"), move |mut stream, received| {
+        let mut header = [0; HEADER_LEN];
+        while stream.read_exact(&mut header).is_ok() {
+            let (len, _) = decode_header(&header).unwrap();
+            let mut payload = vec![0; len];
+            stream.read_exact(&mut payload).unwrap();
+            received
+                .lock()
+                .unwrap()
+                .push(String::from_utf8(payload).unwrap());
+
+            if let Some((reply_type, reply)) = replies.next() {
+                stream.write_all(&encode(reply_type, reply)).unwrap();
+            }
+        }
+    })
+}
+
+#[test]
+fn test_run_command() {
+    let fake = fake_ipc(
+        "run-command",
+        vec![
+            (RUN_COMMAND, "[{\"success\":true}]"),
+            (
+                RUN_COMMAND,
+                "[{\"success\":false,\"error\":\"no such window\"}]",
+            ),
+            (RUN_COMMAND, "[{\"success\":true}]"),
+        ],
+    );
+
+    assert_eq!(focus_on(&fake.path, "3"), 0);
+    assert_eq!(close_focused(&fake.path), 6);
+    assert_eq!(move_to(&fake.path, "web"), 0);
+
+    assert_eq!(
+        fake.received(),
+        [
+            "workspace number 3",
+            "kill",
+            "move container to workspace \"web\""
+        ]
+    );
+    fake.remove();
+    assert_eq!(focus_on(&fake.path, "3"), 5);
+}
+
+#[test]
+fn test_is_program_window() {
+    let sway: Value = serde_json::from_str("{\"id\":4,\"app_id\":\"Alacritty\"}").unwrap();
+    let i3: Value = serde_json::from_str(
+        "{\"id\":5,\"window_properties\":{\"class\":\"Brave-browser\",\"instance\":\"brave-browser\"}}",
+    )
+    .unwrap();
+
+    assert!(is_program_window(&sway, "alacritty"));
+    assert!(is_program_window(
+        &i3,
+        &wm_lib::window_name("/usr/bin/brave-browser --incognito", None)
+    ));
+    assert!(!is_program_window(&i3, "brave"));
+}
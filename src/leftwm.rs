@@ -1,14 +1,73 @@
-use log::error;
+use log::{error, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::{thread, time};
 use tokio::fs::write;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::net::UnixStream;
 use tokio::task;
+use tokio::time::timeout;
 use xdg::BaseDirectories;
 
 use crate::common;
-use crate::config::OptGenRes;
+use crate::config::{GenericRes, OptGenRes};
 use crate::wm_lib;
 
+#[cfg(test)]
+use crate::fake_socket::{FakeSocket, Received};
+
+/// how long leftwm gets to show the result of a command in its state.
+const VERIFY_TIMEOUT: time::Duration = time::Duration::from_millis(1000);
+
+/// leftwm's state, as it writes it to `current_state.sock` (the same json `leftwm-state`
+/// prints).
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct State {
+    window_title: Option<String>,
+    desktop_names: Vec<String>,
+    viewports: Vec<Viewport>,
+    active_desktop: Vec<String>,
+    working_tags: Vec<String>,
+}
+
+/// a workspace and the tag it shows.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Viewport {
+    tag: String,
+}
+
+impl State {
+    /// returns the id (counted from one) of the tag `desktop`, which is a tag name or else a
+    /// number. numbers past the last tag are only taken when the tags aren't known.
+    fn tag_id(&self, desktop: &str) -> Option<usize> {
+        let desktop = desktop.trim();
+
+        if let Some(i) = self.desktop_names.iter().position(|name| name == desktop) {
+            return Some(i + 1);
+        }
+
+        let id = desktop.parse::<usize>().ok().filter(|id| *id > 0)?;
+        (self.desktop_names.is_empty() || id <= self.desktop_names.len()).then_some(id)
+    }
+
+    /// returns the name of the tag with the id `id`.
+    fn tag_name(&self, id: usize) -> Option<&str> {
+        self.desktop_names
+            .get(id.checked_sub(1)?)
+            .map(String::as_str)
+    }
+
+    /// returns true if workspace `workspace` shows the tag `tag`.
+    fn shows(&self, workspace: usize, tag: &str) -> bool {
+        self.viewports
+            .get(workspace)
+            .is_some_and(|viewport| viewport.tag == tag)
+    }
+}
+
 pub async fn leftwm_switch(cmd: &str, args: &str) -> OptGenRes {
     match cmd {
         "move-to" => Some((move_to(args).await, None)),
@@ -16,18 +75,32 @@ pub async fn leftwm_switch(cmd: &str, args: &str) -> OptGenRes {
         "open-at" | "open-on" => Some((open_on_desktop(args).await, None)),
         "focus-on" => Some((focus_on(args).await, None)),
         "load-layout" => Some((load_layout(args).await, None)),
+        "apply-workspaces" => Some((apply_workspaces(args).await, None)),
+        "query-desktops" => Some(query_desktops().await),
+        "query-windows" => Some(query_windows().await),
         _ => None,
     }
 }
 
-pub fn get_cmd_file() -> Option<PathBuf> {
-    let file_name = "command-0.pipe";
+/// where the tests put their fake leftwm, in place of $XDG_RUNTIME_DIR. (which would change it
+/// for every other test too)
+#[cfg(test)]
+static TEST_RUNTIME_DIR: std::sync::Mutex<Option<PathBuf>> = std::sync::Mutex::new(None);
+
+/// finds `file_name` in leftwm's runtime dir.
+fn get_runtime_file(file_name: &str) -> Option<PathBuf> {
+    #[cfg(test)]
+    if let Some(dir) = TEST_RUNTIME_DIR.lock().unwrap().as_ref() {
+        let path = dir.join("leftwm").join(file_name);
+        return path.exists().then_some(path);
+    }
+
     match BaseDirectories::with_prefix("leftwm") {
         Ok(run_dir) => {
             let dirs = run_dir.find_runtime_file(file_name);
 
             if dirs.is_none() {
-                error!("Couldn't find the leftwm command.pipe file.");
+                error!("Couldn't find the leftwm {file_name} file.");
             }
 
             dirs
@@ -39,6 +112,140 @@ pub fn get_cmd_file() -> Option<PathBuf> {
     }
 }
 
+pub fn get_cmd_file() -> Option<PathBuf> {
+    get_runtime_file("command-0.pipe")
+}
+
+/// a connection to leftwm's state socket. leftwm writes its whole state as a line of json when
+/// a client connects and again every time it changes.
+struct States {
+    lines: Lines<BufReader<UnixStream>>,
+}
+
+impl States {
+    async fn connect() -> Result<States, u8> {
+        let Some(path) = get_runtime_file("current_state.sock") else {
+            return Err(5);
+        };
+
+        match UnixStream::connect(&path).await {
+            Ok(stream) => Ok(States {
+                lines: BufReader::new(stream).lines(),
+            }),
+            Err(e) => {
+                error!("could not connect to leftwm's state socket {path:?}. got error: \"{e}\"");
+                Err(5)
+            }
+        }
+    }
+
+    /// reads the next state. returns 6 if leftwm's state can't be read.
+    async fn next(&mut self) -> Result<State, u8> {
+        match self.lines.next_line().await {
+            Ok(Some(line)) => serde_json::from_str(&line).map_err(|e| {
+                error!("could not read leftwm's state. got error: \"{e}\"");
+                6
+            }),
+            Ok(None) => {
+                error!("leftwm closed its state socket");
+                Err(6)
+            }
+            Err(e) => {
+                error!("could not read leftwm's state. got error: \"{e}\"");
+                Err(6)
+            }
+        }
+    }
+
+    /// waits until leftwm reports a state that passes `check`.
+    async fn wait_for(&mut self, check: impl Fn(&State) -> bool) -> Result<(), u8> {
+        loop {
+            if check(&self.next().await?) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// returns leftwm's current state.
+async fn get_state() -> Result<State, u8> {
+    States::connect().await?.next().await
+}
+
+/// sends `cmd` and waits (up to `VERIFY_TIMEOUT`) for leftwm's state to pass `check`. returns 6
+/// if it doesn't. the command is still sent, without being checked, if leftwm's state can't be
+/// read.
+async fn send_checked(cmd: &str, check: impl Fn(&State) -> bool) -> u8 {
+    // leftwm sends its current state as soon as it's connected to, which is from before the
+    // command. so only the states after it are checked, unless it passes already.
+    let before = match States::connect().await {
+        Ok(mut states) => match timeout(VERIFY_TIMEOUT, states.next()).await {
+            Ok(Ok(before)) => Some((states, before)),
+            _ => None,
+        },
+        Err(_) => None,
+    };
+    let mut states = match before {
+        Some((states, before)) => (!check(&before)).then_some(states),
+        None => {
+            warn!("can't read leftwm's state, \"{cmd}\" won't be checked");
+            None
+        }
+    };
+
+    let ec = send_cmd(cmd).await;
+    let Some(states) = states.as_mut().filter(|_| ec == 0) else {
+        return ec;
+    };
+
+    match timeout(VERIFY_TIMEOUT, states.wait_for(check)).await {
+        Ok(Ok(())) => 0,
+        Ok(Err(ec)) => ec,
+        Err(_) => {
+            error!("leftwm didn't show the result of \"{cmd}\" within {VERIFY_TIMEOUT:?}");
+            6
+        }
+    }
+}
+
+/// resolves `desktop` to a tag id and its name. the name is None if leftwm's state can't be
+/// read, in which case `desktop` has to be a number.
+async fn resolve_tag(desktop: &str) -> Result<(usize, Option<String>), u8> {
+    let state = get_state().await.unwrap_or_default();
+
+    match state.tag_id(desktop) {
+        Some(id) => Ok((id, state.tag_name(id).map(String::from))),
+        None => {
+            error!("there is no tag \"{desktop}\"");
+            Err(7)
+        }
+    }
+}
+
+/// responds with the names of all desktops (tags), one per line.
+async fn query_desktops() -> GenericRes {
+    match get_state().await {
+        Ok(state) => (0, Some(state.desktop_names.join("\n"))),
+        Err(ec) => (ec, None),
+    }
+}
+
+/// responds with the windows leftwm reports as "desktop<tab>title", one per line. leftwm only
+/// reports the title of the focused window.
+async fn query_windows() -> GenericRes {
+    match get_state().await {
+        Ok(state) => {
+            let desktop = state.active_desktop.first().cloned().unwrap_or_default();
+            let windows = state
+                .window_title
+                .map(|title| format!("{desktop}\t{title}"))
+                .unwrap_or_default();
+            (0, Some(windows))
+        }
+        Err(ec) => (ec, None),
+    }
+}
+
 async fn load_layout(args: &str) -> u8 {
     // loads a layout file and configures the system apropiately.
 
@@ -47,7 +254,48 @@ async fn load_layout(args: &str) -> u8 {
         Err(n) => return n,
     };
 
-    load_from_yaml(layout_yaml.desktops).await
+    let error_code = load_from_yaml(layout_yaml.desktops).await;
+
+    if error_code > 0 {
+        return error_code;
+    }
+
+    match layout_yaml.workspaces {
+        Some(workspaces) => set_workspaces(&workspaces).await,
+        None => 0,
+    }
+}
+
+/// sends tags to the workspaces described by the layout's `workspaces` map.
+async fn set_workspaces(workspaces: &HashMap<i32, i32>) -> u8 {
+    let state = get_state().await.unwrap_or_default();
+
+    for (workspace, tag) in workspaces {
+        let name = usize::try_from(*tag)
+            .ok()
+            .and_then(|tag| state.tag_name(tag + 1));
+        let ec = send_checked(
+            &format!("SendWorkspaceToTag {workspace} {tag}"),
+            |state| match (usize::try_from(*workspace), name) {
+                (Ok(workspace), Some(name)) => state.shows(workspace, name),
+                _ => true,
+            },
+        )
+        .await;
+        if ec > 0 {
+            error!("could not send workspace {workspace} to tag {tag}");
+            return ec;
+        }
+    }
+
+    0
+}
+
+async fn apply_workspaces(args: &str) -> u8 {
+    match wm_lib::get_workspaces(args) {
+        Ok(workspaces) => set_workspaces(&workspaces).await,
+        Err(n) => n,
+    }
 }
 
 async fn load_from_yaml(layouts: Vec<wm_lib::DesktopLayout>) -> u8 {
@@ -149,8 +397,16 @@ fn get_progs(programs: &Vec<wm_lib::Program>) -> Vec<(String, Option<u8>)> {
 }
 
 async fn move_to(args: &str) -> u8 {
-    // TODO: add args check (ie return 7 if to many or few)
-    send_cmd(&format!("SendWindowToTag {args}")).await
+    let (id, name) = match resolve_tag(args).await {
+        Ok(tag) => tag,
+        Err(ec) => return ec,
+    };
+
+    send_checked(&format!("SendWindowToTag {id}"), |state| match &name {
+        Some(name) => state.working_tags.contains(name),
+        None => true,
+    })
+    .await
 }
 
 async fn close_focused() -> u8 {
@@ -166,17 +422,8 @@ async fn open_on_desktop(args: &str) -> u8 {
         }
     };
 
-    // TODO: read config file and get desktop i, that way we can treat desktop as a name.
-    let desktop = match desktop.parse::<i32>() {
-        Ok(i) => format!("{}", i - 1),
-        Err(e) => {
-            error!("could not interpret  as a number. got error: \"{e}\"");
-            return 2;
-        }
-    };
-
     // TODO: add a way to specify workspace (ie. which monitor should go to the tag).
-    let tag_switch_ec = focus_on(&desktop).await; // send_cmd(&format!("SendWorkspaceToTag 0 {desktop}")).await;
+    let tag_switch_ec = focus_on(desktop).await;
     if tag_switch_ec > 0 {
         return tag_switch_ec;
     }
@@ -185,7 +432,19 @@ async fn open_on_desktop(args: &str) -> u8 {
 }
 
 async fn focus_on(args: &str) -> u8 {
-    send_cmd(&format!("SendWorkspaceToTag 0 {args}")).await
+    let (id, name) = match resolve_tag(args).await {
+        Ok(tag) => tag,
+        Err(ec) => return ec,
+    };
+
+    send_checked(
+        &format!("SendWorkspaceToTag 0 {}", id - 1),
+        |state| match &name {
+            Some(name) => state.shows(0, name),
+            None => true,
+        },
+    )
+    .await
 }
 
 async fn send_cmd(cmd: &str) -> u8 {
@@ -206,3 +465,97 @@ async fn send_cmd(cmd: &str) -> u8 {
         0
     }
 }
+
+#[test]
+fn test_state() {
+    let state: State = serde_json::from_str(
+        r#"{"window_title":"vim","desktop_names":["1","2","web"],"viewports":[{"tag":"web","x":0,"y":0,"h":1080,"w":1920,"layout":"MainAndDeck"}],"active_desktop":["web"],"working_tags":["1","web"],"urgent_tags":[]}"#,
+    )
+    .unwrap();
+
+    assert_eq!(state.tag_id("web"), Some(3));
+    assert_eq!(state.tag_id("2"), Some(2));
+    assert_eq!(state.tag_id("5"), None);
+    assert_eq!(State::default().tag_id("5"), Some(5));
+    assert_eq!(state.tag_id("mail"), None);
+    assert_eq!(state.tag_name(3), Some("web"));
+    assert!(state.shows(0, "web"));
+    assert!(!state.shows(1, "web"));
+}
+
+/// makes a fake leftwm in a temp dir, with a command pipe and a state socket that sends `state`
+/// to every client, and points `get_runtime_file` at it. returns the dir, the state socket,
+/// and the commands written to the pipe.
+#[cfg(test)]
+fn fake_leftwm(state: &'static str) -> (PathBuf, FakeSocket, Received) {
+    use std::ffi::CString;
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+
+    let run_dir = std::env::temp_dir().join(format!("auto-desk-leftwm-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&run_dir);
+    std::fs::create_dir_all(run_dir.join("leftwm")).unwrap();
+    *TEST_RUNTIME_DIR.lock().unwrap() = Some(run_dir.clone());
+
+    let pipe = run_dir.join("leftwm/command-0.pipe");
+    let c_pipe = CString::new(pipe.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_pipe.as_ptr(), 0o600) }, 0);
+    let received = Received::default();
+    let tmp_received = received.clone();
+    thread::spawn(move || {
+        while let Ok(commands) = std::fs::read_to_string(&pipe) {
+            let mut received = tmp_received.lock().unwrap();
+            received.extend(commands.lines().map(String::from));
+        }
+    });
+
+    let states = FakeSocket::at(
+        run_dir.join("leftwm/current_state.sock"),
+        move |mut stream, _| {
+            let _ = writeln!(stream, "{state}");
+        },
+    );
+
+    (run_dir, states, received)
+}
+
+#[tokio::test]
+async fn test_fake_leftwm() {
+    let (run_dir, states, received) = fake_leftwm(
+        r#"{"window_title":"vim","desktop_names":["1","2","web"],"viewports":[{"tag":"web"}],"active_desktop":["web"],"working_tags":["web"]}"#,
+    );
+    // the pipe is read on another thread, so commands show up a moment after they're sent.
+    let sent = |count: usize| {
+        let deadline = time::Instant::now() + VERIFY_TIMEOUT;
+        while received.lock().unwrap().len() < count && time::Instant::now() < deadline {
+            thread::sleep(time::Duration::from_millis(10));
+        }
+        received.lock().unwrap().clone()
+    };
+
+    assert_eq!(focus_on("web").await, 0);
+    assert_eq!(move_to("web").await, 0);
+    // the fake state never changes from "web", so this doesn't check out.
+    assert_eq!(focus_on("2").await, 6);
+    assert_eq!(query_windows().await, (0, Some("web\tvim".to_string())));
+    assert_eq!(focus_on("mail").await, 7);
+    assert_eq!(
+        sent(3),
+        [
+            "SendWorkspaceToTag 0 2",
+            "SendWindowToTag 3",
+            "SendWorkspaceToTag 0 1"
+        ]
+    );
+
+    // without leftwm's state numbers still work, unchecked.
+    states.remove();
+    assert_eq!(focus_on("1").await, 0);
+    assert_eq!(focus_on("web").await, 7);
+    assert_eq!(sent(4)[3], "SendWorkspaceToTag 0 0");
+    assert_eq!(query_desktops().await, (5, None));
+
+    std::fs::remove_file(run_dir.join("leftwm/command-0.pipe")).unwrap();
+    assert_eq!(close_focused().await, 5);
+    std::fs::remove_dir_all(&run_dir).unwrap();
+}
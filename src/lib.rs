@@ -1,12 +1,24 @@
 pub mod bspwm;
+pub mod commands;
 pub mod common;
 pub mod config;
-// pub mod events;
-// pub mod hooks;
+#[cfg(feature = "hooks")]
+pub mod events;
+#[cfg(feature = "ewmh")]
+pub mod ewmh;
+#[cfg(test)]
+mod fake_socket;
+pub mod herbstluftwm;
+#[cfg(feature = "hooks")]
+pub mod hooks;
+pub mod hyprland;
+pub mod i3;
 pub mod client;
 pub mod leftwm;
+pub mod mock;
 pub mod msgs;
 pub mod qtile;
+pub mod security;
 pub mod server;
 pub mod wm_lib;
 
@@ -0,0 +1,211 @@
+use crate::config::{GenericRes, OptGenRes};
+use crate::wm_lib;
+use crate::wm_lib::Program;
+use log::{error, info};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// the pid the first window of a launched program gets, the ones after it count up.
+const FIRST_PID: u32 = 10000;
+
+/// the state of the mock window manager, shared by every server in the process.
+static MOCK: Mutex<MockWm> = Mutex::new(MockWm::new());
+
+/// a window the mock window manager pretends to manage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub id: usize,
+    pub pid: u32,
+    pub class: String,
+    pub desktop: String,
+}
+
+/// a window manager that only keeps track of what it was asked to do. (`wm = "mock"`)
+#[derive(Debug)]
+pub struct MockWm {
+    /// every command it handled, as (command, arguments).
+    pub calls: Vec<(String, String)>,
+    pub windows: Vec<Window>,
+    pub focused_window: Option<usize>,
+    pub focused_desktop: Option<String>,
+    /// the desktop each monitor shows, set from a layout's `workspaces` map.
+    pub workspaces: BTreeMap<i32, i32>,
+    next_id: usize,
+}
+
+impl MockWm {
+    const fn new() -> MockWm {
+        MockWm {
+            calls: Vec::new(),
+            windows: Vec::new(),
+            focused_window: None,
+            focused_desktop: None,
+            workspaces: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// forgets every call and window.
+    pub fn reset(&mut self) {
+        *self = MockWm::new();
+    }
+
+    /// adds a window as if the program `pid` (with the wm_class `class`) opened it on
+    /// `desktop`. the new window gets focus. returns its id.
+    pub fn open_window(&mut self, pid: u32, class: &str, desktop: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.windows.push(Window {
+            id,
+            pid,
+            class: class.to_string(),
+            desktop: desktop.trim().to_string(),
+        });
+        self.focused_window = Some(id);
+
+        id
+    }
+
+    /// opens a window for `program`, which is the program's wm_class or else its name.
+    fn launch(&mut self, program: &Program, desktop: &str) -> usize {
+        let pid = FIRST_PID + self.next_id as u32;
+        let class = wm_lib::window_name(&program.name, program.wm_class.as_deref());
+
+        info!("pretending to run {} on desktop {desktop}", program.name);
+        self.open_window(pid, &class, desktop)
+    }
+
+    fn focused(&mut self) -> Option<&mut Window> {
+        let id = self.focused_window?;
+        self.windows.iter_mut().find(|window| window.id == id)
+    }
+
+    /// returns the desktops that have windows or focus, in the order they were first used.
+    fn desktops(&self) -> Vec<&str> {
+        let mut desktops: Vec<&str> = Vec::new();
+
+        for desktop in self
+            .windows
+            .iter()
+            .map(|window| window.desktop.as_str())
+            .chain(self.focused_desktop.as_deref())
+        {
+            if !desktops.contains(&desktop) {
+                desktops.push(desktop);
+            }
+        }
+
+        desktops
+    }
+}
+
+/// locks the mock window manager's state.
+pub fn mock() -> MutexGuard<'static, MockWm> {
+    MOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn open_on_desktop(wm: &mut MockWm, args: &str) -> u8 {
+    let Some((program, desktop)) = args.trim().rsplit_once(' ') else {
+        error!("open-at needs a program and a desktop");
+        return 7;
+    };
+
+    let program = Program {
+        name: program.to_string(),
+        state: None,
+        wm_class: None,
+        args: None,
+        delay: None,
+    };
+    wm.launch(&program, desktop);
+
+    0
+}
+
+fn move_to(wm: &mut MockWm, desktop: &str) -> u8 {
+    match wm.focused() {
+        Some(window) => {
+            window.desktop = desktop.trim().to_string();
+            0
+        }
+        None => {
+            error!("there is no focused window to move");
+            6
+        }
+    }
+}
+
+fn close_focused(wm: &mut MockWm) -> u8 {
+    let Some(id) = wm.focused_window.take() else {
+        error!("there is no focused window to close");
+        return 6;
+    };
+
+    wm.windows.retain(|window| window.id != id);
+    0
+}
+
+fn load_layout(wm: &mut MockWm, args: &str) -> u8 {
+    let layout = match wm_lib::get_layout(args) {
+        Ok(layout) => layout,
+        Err(n) => return n,
+    };
+
+    for desktop in layout.desktops {
+        if desktop.clear.unwrap_or(false) {
+            wm.windows
+                .retain(|window| window.desktop != desktop.desktop);
+        }
+
+        for program in &desktop.programs {
+            wm.launch(program, &desktop.desktop);
+        }
+    }
+
+    wm.workspaces.extend(layout.workspaces.unwrap_or_default());
+    0
+}
+
+fn apply_workspaces(wm: &mut MockWm, args: &str) -> u8 {
+    match wm_lib::get_workspaces(args) {
+        Ok(workspaces) => {
+            wm.workspaces.extend(workspaces);
+            0
+        }
+        Err(n) => n,
+    }
+}
+
+/// responds with every window as "desktop<tab>class", one per line.
+fn query_windows(wm: &MockWm) -> GenericRes {
+    let windows: Vec<String> = wm
+        .windows
+        .iter()
+        .map(|window| format!("{}\t{}", window.desktop, window.class))
+        .collect();
+
+    (0, Some(windows.join("\n")))
+}
+
+pub async fn mock_switch(cmd: &str, args: &str) -> OptGenRes {
+    let mut wm = mock();
+
+    let res = match cmd {
+        "move-to" => (move_to(&mut wm, args), None),
+        "close-focused" => (close_focused(&mut wm), None),
+        "open-at" | "open-on" => (open_on_desktop(&mut wm, args), None),
+        "focus-on" => {
+            wm.focused_desktop = Some(args.trim().to_string());
+            (0, None)
+        }
+        "load-layout" => (load_layout(&mut wm, args), None),
+        "apply-workspaces" => (apply_workspaces(&mut wm, args), None),
+        "query-desktops" => (0, Some(wm.desktops().join("\n"))),
+        "query-windows" => query_windows(&wm),
+        _ => return None,
+    };
+
+    wm.calls.push((cmd.to_string(), args.to_string()));
+    Some(res)
+}
@@ -1,3 +1,22 @@
 pub enum EventCmd {
     Exit
-}
\ No newline at end of file
+}
+
+/// returns what an error code from the server means. (see the "exit Code" table in spec.md)
+pub fn code_meaning(ec: u8) -> &'static str {
+    match ec {
+        0 => "no errors",
+        1 => "command not found",
+        2 => "error running command, check logs",
+        3 => "process error-ed out while executing supporting command",
+        4 => "there was an error with the main command",
+        5 => "error connecting to wm socket",
+        6 => "wm error",
+        7 => "too few/many arguments",
+        8 => "internal communication problem",
+        9 => "hook adding error",
+        10 => "hook rm error",
+        11 => "permission denied",
+        _ => "unknown error code",
+    }
+}
@@ -1,9 +1,26 @@
 use crate::common;
-use crate::config::OptGenRes;
+use crate::config::{GenericRes, OptGenRes};
 use crate::wm_lib;
-use log::{debug, error, trace};
+use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+use crate::fake_socket::{read_to_end, FakeSocket};
+
+/// how long launched programs get to open their windows before they're left where they opened.
+const WINDOW_TIMEOUT: Duration = Duration::from_millis(5000);
+/// how long to wait for qtile to reply to a call.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(2000);
+/// the group auto_desk_api.py hides the windows of cleared groups in.
+const HIDDEN_GROUP: &str = "hidden";
 
 type WMClass = String;
 type Desktop = String;
@@ -19,7 +36,7 @@ pub enum QtileAPI {
 }
 
 // #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct QtileCmdData {
     pub rules: Rules,
     pub queue: Programs,
@@ -36,10 +53,7 @@ impl QtileCmdData {
     }
 
     fn add_clear(&mut self, to_clear: Option<bool>, data: &str) {
-        let clear = match to_clear {
-            Some(b) => b,
-            _ => false,
-        };
+        let clear = to_clear.unwrap_or_default();
         self.clear.insert(data.to_string(), clear);
     }
 
@@ -50,6 +64,15 @@ impl QtileCmdData {
         });
     }
 
+    /// what's left of the layout for auto_desk_api.py when the server moves the windows and
+    /// clears the groups over qtile's socket, which is nothing.
+    pub fn handled(&self) -> QtileCmdData {
+        QtileCmdData {
+            queue: self.queue.clone(),
+            ..QtileCmdData::new()
+        }
+    }
+
     fn add_rules(&mut self, wmc: &str, desktop: &str) {
         match self.rules.get_mut(wmc) {
             Some(rules) => {
@@ -134,8 +157,9 @@ pub fn auto_move(args: &str, layout: &mut QtileCmdData) -> Result<Option<String>
     Ok(Some(location))
 }
 
-/// open-at
-pub fn open_on_desktop(_spath: &str, args: &str, layout: &mut QtileCmdData) -> u8 {
+/// open-at. the window is moved over qtile's socket when it can be found, otherwise the rule is
+/// kept for auto_desk_api.py. (only one of them moves it)
+pub fn open_on_desktop(socket: Option<&Path>, args: &str, layout: &mut QtileCmdData) -> u8 {
     let data = args.split(' ').collect::<Vec<&str>>();
 
     if data.len() != 3 {
@@ -144,26 +168,245 @@ pub fn open_on_desktop(_spath: &str, args: &str, layout: &mut QtileCmdData) -> u
 
     let (exe, wm_class, desktop) = (data[0], data[1], data[2]);
 
-    layout.add_rules(wm_class, desktop);
+    if socket.is_none() {
+        layout.add_rules(wm_class, desktop);
+    }
+
+    let known = socket.map(window_ids).unwrap_or_default();
+    let ec = common::open_program(exe);
+
+    if let (0, Some(socket)) = (ec, socket) {
+        let mut rules = HashMap::from([(wm_class.to_string(), vec![desktop.to_string()])]);
+        place_new_windows(socket, &mut rules, known, Instant::now() + WINDOW_TIMEOUT);
+    }
+
+    ec
+}
+
+/// returns qtile's ipc socket, "$XDG_CACHE_HOME/qtile/qtilesocket.<display>", or the first
+/// qtile socket if none is named after this display.
+pub fn get_socket() -> Option<PathBuf> {
+    let cache_dir = std::env::var("XDG_CACHE_HOME")
+        .unwrap_or_else(|_| shellexpand::tilde("~/.cache").to_string());
+    let dir = Path::new(&cache_dir).join("qtile");
+
+    let display = std::env::var("WAYLAND_DISPLAY").or_else(|_| std::env::var("DISPLAY"));
+    if let Some(socket) = display
+        .ok()
+        .map(|display| dir.join(format!("qtilesocket.{display}")))
+        .filter(|socket| socket.exists())
+    {
+        return Some(socket);
+    }
+
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("qtilesocket."))
+        })
+}
+
+/// calls the command `name` on the object picked by `selectors` (eg. `[["group", "2"]]`, or
+/// `[]` for the root) and returns its result. the message is qtile's (selectors, name, args,
+/// kwargs, lifted) call sent as json, which qtile takes on the same socket as its marshalled
+/// messages. returns 5 if the socket can't be used and 6 if qtile reports an error.
+fn call(socket: &Path, selectors: Value, name: &str, args: Value) -> Result<Value, u8> {
+    let message = json!([selectors, name, args, {}, true]).to_string();
+
+    let mut reply = String::new();
+    UnixStream::connect(socket)
+        .and_then(|mut stream| {
+            stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+            stream.write_all(message.as_bytes())?;
+            stream.shutdown(Shutdown::Write)?;
+            stream.read_to_string(&mut reply)
+        })
+        .map_err(|e| {
+            error!("could not talk to qtile over {socket:?}. got error: \"{e}\"");
+            5
+        })?;
+
+    match serde_json::from_str::<Value>(&reply) {
+        Ok(Value::Array(reply)) if reply.first() == Some(&json!(0)) => {
+            Ok(reply.get(1).cloned().unwrap_or_default())
+        }
+        Ok(reply) => {
+            error!("qtile could not run \"{name}\": {reply}");
+            Err(6)
+        }
+        Err(e) => {
+            error!("could not read qtile's reply to \"{name}\". got error: \"{e}\"");
+            Err(6)
+        }
+    }
+}
 
-    common::open_program(exe)
+/// like `call` but only returns the error code.
+fn send(socket: &Path, selectors: Value, name: &str, args: Value) -> u8 {
+    call(socket, selectors, name, args).err().unwrap_or(0)
 }
 
 /// focus-on
-pub fn focus_on(spath: &str, args: &str) -> u8 {
-    trace!("focus_on");
-    debug!("spath => {}", spath);
-    debug!("args => {}", args);
-    // TODO: write this
+fn focus_on(socket: &Path, group: &str) -> u8 {
+    send(
+        socket,
+        json!([["group", group.trim()]]),
+        "toscreen",
+        json!([]),
+    )
+}
+
+fn move_to(socket: &Path, group: &str) -> u8 {
+    send(
+        socket,
+        json!([["window", null]]),
+        "togroup",
+        json!([group.trim()]),
+    )
+}
+
+fn close_focused(socket: &Path) -> u8 {
+    send(socket, json!([["window", null]]), "kill", json!([]))
+}
+
+/// responds with the names of all desktops (groups), one per line.
+fn query_desktops(socket: &Path) -> GenericRes {
+    match call(socket, json!([]), "groups", json!([])) {
+        Ok(Value::Object(groups)) => (
+            0,
+            Some(groups.keys().cloned().collect::<Vec<_>>().join("\n")),
+        ),
+        Ok(_) => (6, None),
+        Err(ec) => (ec, None),
+    }
+}
+
+/// responds with every window as "group<tab>name", one per line.
+fn query_windows(socket: &Path) -> GenericRes {
+    match windows(socket) {
+        Ok(windows) => {
+            let lines: Vec<String> = windows
+                .iter()
+                .map(|window| {
+                    format!(
+                        "{}\t{}",
+                        window["group"].as_str().unwrap_or_default(),
+                        window["name"].as_str().unwrap_or_default()
+                    )
+                })
+                .collect();
+            (0, Some(lines.join("\n")))
+        }
+        Err(ec) => (ec, None),
+    }
+}
+
+/// returns qtile's info on every window.
+fn windows(socket: &Path) -> Result<Vec<Value>, u8> {
+    match call(socket, json!([]), "windows", json!([]))? {
+        Value::Array(windows) => Ok(windows),
+        _ => Err(6),
+    }
+}
+
+fn window_ids(socket: &Path) -> Vec<Value> {
+    windows(socket)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|window| window["id"].clone())
+        .collect()
+}
+
+/// moves each new window (one that isn't in `known`) whose wm_class has a rule to the rule's
+/// group, until every rule is used or `deadline` passes.
+fn place_new_windows(socket: &Path, rules: &mut Rules, mut known: Vec<Value>, deadline: Instant) {
+    rules.retain(|_, groups| !groups.is_empty());
+
+    while !rules.is_empty() && Instant::now() < deadline {
+        for window in windows(socket).unwrap_or_default() {
+            if known.contains(&window["id"]) {
+                continue;
+            }
+
+            let classes = window["wm_class"].as_array().cloned().unwrap_or_default();
+            let group = classes
+                .iter()
+                .filter_map(Value::as_str)
+                .find_map(|class| rules.get_mut(class).and_then(Vec::pop));
+            if let Some(group) = group {
+                info!("moving window {} to group {group}", window["id"]);
+                send(
+                    socket,
+                    json!([["window", window["id"]]]),
+                    "togroup",
+                    json!([group]),
+                );
+                known.push(window["id"].clone());
+            }
+        }
+
+        rules.retain(|_, groups| !groups.is_empty());
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    if !rules.is_empty() {
+        warn!("no windows showed up for {:?}", rules.keys());
+    }
+}
+
+/// hides the windows of `group` in the hidden group, like auto_desk_api.py does.
+fn clear_group(socket: &Path, group: &str) -> u8 {
+    for window in windows(socket).unwrap_or_default() {
+        if window["group"].as_str() == Some(group) {
+            let ec = send(
+                socket,
+                json!([["window", window["id"]]]),
+                "togroup",
+                json!([HIDDEN_GROUP]),
+            );
+            if ec > 0 {
+                return ec;
+            }
+        }
+    }
+
     0
 }
 
-pub fn make_cmd_data(fname: &str) -> Result<QtileCmdData, u8> {
-    let layouts = match wm_lib::get_layout(fname) {
-        Ok(layout) => layout,
-        Err(n) => return Err(n),
+/// launches the programs of a loaded layout. if qtile's `socket` was found the groups are
+/// cleared and the new windows moved over it, otherwise that's left to auto_desk_api.py.
+pub fn launch_layout(socket: Option<PathBuf>, data: QtileCmdData) {
+    let known = match &socket {
+        Some(socket) => {
+            for (group, _) in data.clear.iter().filter(|(_, clear)| **clear) {
+                if clear_group(socket, group) > 0 {
+                    error!("could not clear group {group}");
+                }
+            }
+            window_ids(socket)
+        }
+        None => {
+            debug!("qtile's socket wasn't found, leaving the layout to auto_desk_api.py");
+            Vec::new()
+        }
     };
 
+    for program in &data.queue {
+        common::open_program(program);
+    }
+
+    if let Some(socket) = socket {
+        let mut rules = data.rules;
+        place_new_windows(&socket, &mut rules, known, Instant::now() + WINDOW_TIMEOUT);
+    }
+}
+
+pub fn make_cmd_data(fname: &str) -> Result<QtileCmdData, u8> {
+    let layouts = wm_lib::get_layout(fname)?;
+
     let mut payload_struct = QtileCmdData::new();
 
     for desktop in layouts.desktops {
@@ -226,11 +469,30 @@ pub async fn qtile_switch(
     spath: &str,
     layout: &mut QtileCmdData,
 ) -> OptGenRes {
+    trace!("qtile_switch, spath => {spath}");
+    let socket = get_socket();
+
+    if cmd == "open-at" || cmd == "open-on" {
+        return Some((open_on_desktop(socket.as_deref(), args, layout), None));
+    }
+    if !matches!(
+        cmd,
+        "move-to" | "close-focused" | "focus-on" | "query-desktops" | "query-windows"
+    ) {
+        return None;
+    }
+
+    let Some(socket) = socket else {
+        error!("could not find qtile's socket in $XDG_CACHE_HOME/qtile");
+        return Some((5, Some("could not find qtile's socket".to_string())));
+    };
+
     match cmd {
-        // "move-to" => Some(move_to(spath, args)),
-        // "close-focused" => Some(close_focused(spath)),
-        "open-at" | "open-on" => Some((open_on_desktop(spath, args, layout), None)),
-        "focus-on" => Some((focus_on(spath, args), None)),
+        "move-to" => Some((move_to(&socket, args), None)),
+        "close-focused" => Some((close_focused(&socket), None)),
+        "focus-on" => Some((focus_on(&socket, args), None)),
+        "query-desktops" => Some(query_desktops(&socket)),
+        "query-windows" => Some(query_windows(&socket)),
         _ => None,
     }
 }
@@ -260,3 +522,23 @@ pub async fn qtile_api(cmd: &str, args: &str, layout: &mut QtileCmdData) -> Opti
         _ => None,
     }
 }
+
+#[test]
+fn test_call() {
+    let mut replies = [r#"[0, null]"#, r#"[1, "No such group"]"#].into_iter();
+    let fake = FakeSocket::answering("qtile", read_to_end, move |_| {
+        replies.next().unwrap_or_default().into()
+    });
+
+    assert_eq!(move_to(&fake.path, "web"), 0);
+    assert_eq!(focus_on(&fake.path, "mail"), 6);
+    assert_eq!(
+        fake.received(),
+        [
+            r#"[[["window",null]],"togroup",["web"],{},true]"#,
+            r#"[[["group","mail"]],"toscreen",[],{},true]"#
+        ]
+    );
+    fake.remove();
+    assert_eq!(close_focused(&fake.path), 5);
+}
@@ -0,0 +1,149 @@
+use crate::config::{ClientPolicy, Policy, Security};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tokio::net::UnixStream;
+
+/// the process on the other end of a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    pub uid: u32,
+    /// read from "/proc/<pid>/exe", which only works for our own user's processes (or as root).
+    pub exe: Option<PathBuf>,
+}
+
+impl Peer {
+    /// gets the credentials of the process connected to `stream` with `SO_PEERCRED`.
+    pub fn of(stream: &UnixStream) -> std::io::Result<Peer> {
+        let cred = stream.peer_cred()?;
+        let exe = cred
+            .pid()
+            .and_then(|pid| std::fs::read_link(format!("/proc/{pid}/exe")).ok());
+
+        Ok(Peer {
+            uid: cred.uid(),
+            exe,
+        })
+    }
+}
+
+impl Policy {
+    /// returns true if the policy lets a client send `cmd`.
+    pub fn allows(&self, cmd: &str) -> bool {
+        let listed = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| pattern == "*" || pattern == cmd)
+        };
+
+        self.allow.as_deref().is_none_or(listed) && !listed(&self.deny)
+    }
+}
+
+impl ClientPolicy {
+    /// returns true if everything the policy is picked by matches `peer`.
+    fn applies_to(&self, peer: &Peer) -> bool {
+        let uid_matches = self.uid.is_none_or(|uid| uid == peer.uid);
+        let exe_matches = self.exe.as_ref().is_none_or(|exe| {
+            peer.exe.as_deref().is_some_and(|peer_exe| {
+                peer_exe == Path::new(exe) || peer_exe.file_name() == Some(OsStr::new(exe))
+            })
+        });
+
+        uid_matches && exe_matches
+    }
+}
+
+/// checks whether `peer` may send commands at all, before anything is read from it. users other
+/// than `own_uid` are refused unless a client policy names their uid.
+pub fn check_uid(security: &Security, peer: &Peer, own_uid: u32) -> Result<(), String> {
+    if peer.uid != own_uid
+        && !security
+            .clients
+            .iter()
+            .any(|client| client.uid == Some(peer.uid) && client.applies_to(peer))
+    {
+        return Err("only the user running the server can send it commands".to_string());
+    }
+
+    Ok(())
+}
+
+/// checks whether `peer` may send `cmd` over `socket`, which is None for the listen socket or
+/// else the path of one of the `[[security.sockets]]`. the socket's policy and every client
+/// policy that applies to the peer have to allow the command, and the peer has to pass
+/// `check_uid`.
+pub fn check(
+    security: &Security,
+    socket: Option<&str>,
+    peer: &Peer,
+    own_uid: u32,
+    cmd: &str,
+) -> Result<(), String> {
+    check_uid(security, peer, own_uid)?;
+
+    if let Some(socket) = socket {
+        match security.sockets.iter().find(|policy| policy.path == socket) {
+            Some(policy) if policy.policy.allows(cmd) => {}
+            Some(_) => return Err(format!("\"{cmd}\" isn't allowed on this socket")),
+            None => return Err("this socket is no longer in the config".to_string()),
+        }
+    }
+
+    if security
+        .clients
+        .iter()
+        .any(|client| client.applies_to(peer) && !client.policy.allows(cmd))
+    {
+        return Err(format!("this client isn't allowed to send \"{cmd}\""));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_check() {
+    let configs: crate::config::Config = toml::de::from_str(
+        "[[security.sockets]]\npath = \"/tmp/status.sock\"\nallow = [\"status\", \"battery\"]\n\
+         [[security.clients]]\nexe = \"eww\"\ndeny = [\"poweroff\", \"SERVER-EXIT\"]\n\
+         [[security.clients]]\nuid = 1001\nallow = [\"vol-up\"]\n",
+    )
+    .unwrap();
+    let security = &configs.security;
+    let shell = Peer {
+        uid: 1000,
+        exe: Some(PathBuf::from("/usr/bin/bash")),
+    };
+    let eww = Peer {
+        uid: 1000,
+        exe: Some(PathBuf::from("/usr/bin/eww")),
+    };
+    let guest = Peer {
+        uid: 1001,
+        exe: None,
+    };
+
+    assert_eq!(check(security, None, &shell, 1000, "poweroff"), Ok(()));
+    assert!(check(security, None, &eww, 1000, "poweroff").is_err());
+    assert_eq!(check(security, None, &eww, 1000, "vol-up"), Ok(()));
+    assert_eq!(
+        check(security, Some("/tmp/status.sock"), &shell, 1000, "status"),
+        Ok(())
+    );
+    assert!(check(security, Some("/tmp/status.sock"), &shell, 1000, "vol-up").is_err());
+    assert!(check(security, Some("/tmp/gone.sock"), &shell, 1000, "status").is_err());
+    assert_eq!(check(security, None, &guest, 1000, "vol-up"), Ok(()));
+    assert!(check(security, None, &guest, 1000, "mute").is_err());
+    // the guest may connect, what it sends is checked once it's read.
+    assert_eq!(check_uid(security, &guest, 1000), Ok(()));
+    assert!(check(
+        security,
+        None,
+        &Peer {
+            uid: 1002,
+            exe: None
+        },
+        1000,
+        "vol-up"
+    )
+    .is_err());
+}
@@ -1,27 +1,99 @@
 use crate::bspwm;
+use crate::commands;
 use crate::common;
 use crate::config;
 use crate::config::{GenericRes, OptGenRes};
-// use crate::hooks;
+#[cfg(feature = "hooks")]
+use crate::events;
+#[cfg(feature = "ewmh")]
+use crate::ewmh;
+use crate::herbstluftwm;
+#[cfg(feature = "hooks")]
+use crate::hooks;
+use crate::hyprland;
+use crate::i3;
 use crate::leftwm;
-// use crate::msgs;
+use crate::mock;
 use crate::qtile;
+use crate::security;
 use futures::future::BoxFuture;
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
+use std::path::{Path, PathBuf};
 use sysinfo::{ProcessExt, System, SystemExt};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{UnixListener, UnixStream};
+#[cfg(feature = "hooks")]
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
 use tokio::task;
+use tokio::time::{sleep, Duration, Instant};
 
-#[derive(PartialEq)]
+const CONFIG_POLL_TIME: Duration = Duration::from_millis(2000);
+/// how long a client gets to send its command.
+const READ_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// the command that turns a client connection into a stream of events. (eg. "subscribe volume,layout")
+pub const SUBSCRIBE_CMD: &str = "subscribe";
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum WindowManager {
     Qtile,
     Bspwm,
     LeftWM,
+    I3,
+    Sway,
+    Herbstluftwm,
+    /// any other window manager that follows the EWMH spec.
+    Ewmh,
+    Hyprland,
+    /// records commands instead of running them, for tests.
+    Mock,
     NoWM,
     Headless,
 }
 
+impl WindowManager {
+    /// the module that handles this window managers commands. (see `commands::COMMANDS`)
+    pub fn module(&self) -> Option<&'static str> {
+        match self {
+            WindowManager::Qtile => Some("qtile"),
+            WindowManager::Bspwm => Some("bspwm"),
+            WindowManager::LeftWM => Some("leftwm"),
+            WindowManager::I3 => Some("i3"),
+            WindowManager::Sway => Some("sway"),
+            WindowManager::Herbstluftwm => Some("herbstluftwm"),
+            WindowManager::Ewmh => Some("ewmh"),
+            WindowManager::Hyprland => Some("hyprland"),
+            WindowManager::Mock => Some("mock"),
+            WindowManager::NoWM | WindowManager::Headless => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowManager::NoWM => "none",
+            WindowManager::Headless => "headless",
+            wm => wm.module().unwrap_or_default(),
+        }
+    }
+
+    /// returns the window manager called `name` (case insensitive). eg. "bspwm" or "LeftWM".
+    pub fn from_name(name: &str) -> Option<WindowManager> {
+        match name.to_lowercase().as_str() {
+            "qtile" => Some(WindowManager::Qtile),
+            "bspwm" => Some(WindowManager::Bspwm),
+            "leftwm" => Some(WindowManager::LeftWM),
+            "i3" => Some(WindowManager::I3),
+            "sway" => Some(WindowManager::Sway),
+            "herbstluftwm" => Some(WindowManager::Herbstluftwm),
+            "ewmh" => Some(WindowManager::Ewmh),
+            "hyprland" => Some(WindowManager::Hyprland),
+            "mock" => Some(WindowManager::Mock),
+            _ => None,
+        }
+    }
+}
+
 fn make_payload(ec: u8, message: Option<String>) -> Vec<u8> {
     let mut payload = vec![ec, if ec > 0 { 7 } else { 0 }];
     if let Some(mesg) = message {
@@ -62,11 +134,18 @@ async fn write_shutdown(stream: &mut UnixStream, ec: u8, message: Option<String>
     };
 }
 
-async fn read_command(stream: &mut UnixStream) -> String {
+/// reads the command a client sent. gives None if the client doesn't finish sending it within
+/// `READ_TIMEOUT`, so a client can't hold up the server.
+async fn read_command(stream: &mut UnixStream) -> Option<String> {
     let mut command = String::new();
     // stream.set_nonblocking(false);
-    let _ = stream.read_to_string(&mut command).await;
-    command
+    match tokio::time::timeout(READ_TIMEOUT, stream.read_to_string(&mut command)).await {
+        Ok(_) => Some(command),
+        Err(_) => {
+            warn!("a client didn't send its command within {READ_TIMEOUT:?}, dropping it");
+            None
+        }
+    }
 }
 
 async fn switch_board<'t>(
@@ -80,6 +159,10 @@ async fn switch_board<'t>(
     let mut futures: Vec<BoxFuture<'t, OptGenRes>> = Vec::new();
     // let mut futures: Vec<SwitchBoardFuture> = Vec::new();
 
+    if cmd == "list-commands" || cmd == "help" {
+        return commands::list_commands(wm.module(), args.trim());
+    }
+
     match wm {
         WindowManager::Qtile => {
             #[cfg(feature = "qtile")]
@@ -94,6 +177,27 @@ async fn switch_board<'t>(
             futures.push(Box::pin(leftwm::leftwm_switch(cmd, args)));
             // futures.push(Box::pin(leftwm::leftwm_switch(cmd, args, spath)));
         }
+        WindowManager::I3 => {
+            #[cfg(feature = "i3")]
+            futures.push(Box::pin(i3::i3_switch(cmd, args, "i3")));
+        }
+        WindowManager::Sway => {
+            #[cfg(feature = "sway")]
+            futures.push(Box::pin(i3::i3_switch(cmd, args, "sway")));
+        }
+        WindowManager::Herbstluftwm => {
+            #[cfg(feature = "herbstluftwm")]
+            futures.push(Box::pin(herbstluftwm::herbstluftwm_switch(cmd, args)));
+        }
+        WindowManager::Ewmh => {
+            #[cfg(feature = "ewmh")]
+            futures.push(Box::pin(ewmh::ewmh_switch(cmd, args)));
+        }
+        WindowManager::Hyprland => {
+            #[cfg(feature = "hyprland")]
+            futures.push(Box::pin(hyprland::hyprland_switch(cmd, args)));
+        }
+        WindowManager::Mock => futures.push(Box::pin(mock::mock_switch(cmd, args))),
         WindowManager::Headless | WindowManager::NoWM => {}
     }
     // common should be checked last.
@@ -103,6 +207,8 @@ async fn switch_board<'t>(
     futures.push(Box::pin(common::sysctl_switch(cmd)));
     #[cfg(feature = "media")]
     futures.push(Box::pin(common::media_switch(cmd, args)));
+    #[cfg(feature = "bluetooth")]
+    futures.push(Box::pin(common::bluetooth_switch(cmd, args)));
     // #[cfg(feature = "hooks")]
     // futures.push(Box::pin(hooks::hooks_switch(cmd, args, maybe_hook_data)));
 
@@ -112,10 +218,19 @@ async fn switch_board<'t>(
         }
     }
 
-    (
-        1,
-        Some(format!("there is no command by the name of, {cmd}")),
-    )
+    match commands::get_command(cmd) {
+        Some(spec) => (
+            1,
+            Some(format!(
+                "{cmd} is not supported by the running window manager. it's handled by: {}",
+                spec.modules.join(", ")
+            )),
+        ),
+        None => (
+            1,
+            Some(format!("there is no command by the name of, {cmd}")),
+        ),
+    }
 }
 
 fn split_cmd(command: &str) -> (String, String) {
@@ -134,7 +249,7 @@ async fn handle_client_gen(
     mut stream: UnixStream,
     spath: &str,
     layout: &mut qtile::QtileCmdData,
-) {
+) -> u8 {
     // handle comand here
     let (ec, message) = switch_board(wm, &cmd, &args, spath, layout).await;
     // let mesg = match message {
@@ -142,7 +257,8 @@ async fn handle_client_gen(
     //     None =>
     // };
     write_shutdown(&mut stream, ec, message).await;
-    drop(stream)
+    drop(stream);
+    ec
 }
 
 async fn handle_client_qtile(
@@ -153,7 +269,7 @@ async fn handle_client_qtile(
     layout: &mut qtile::QtileCmdData,
     // hook_data: &mut Option<hooks::HookData>,
     spath: &str,
-) -> Option<qtile::QtileCmdData> {
+) -> (u8, Option<qtile::QtileCmdData>) {
     // handle comand here
     match qtile::qtile_api(&cmd, &args, layout).await {
         Some(qtile::QtileAPI::Layout(new_layout)) => {
@@ -161,28 +277,28 @@ async fn handle_client_qtile(
             debug!("Response Code: 0");
             write_shutdown(&mut stream, 0, Some("configured layout".to_string())).await;
             drop(stream);
-            Some(new_layout)
+            (0, Some(new_layout))
         }
         Some(qtile::QtileAPI::Message(message)) => {
             trace!("qtile::qtile_api(...) => qtile::QtileAPI::Message");
             debug!("sending message => {message}");
             write_shutdown(&mut stream, 0, Some(message)).await;
             drop(stream);
-            None
+            (0, None)
         }
         Some(qtile::QtileAPI::Res(ec)) => {
             trace!("qtile::qtile_api(...) => qtile::QtileAPI::Res");
             debug!("Response Code: {ec}");
             write_shutdown(&mut stream, ec, None).await;
             drop(stream);
-            None
+            (ec, None)
         }
         None => {
             trace!("qtile::qtile_api(...) => None");
             let (ec, message) = switch_board(wm, &cmd, &args, spath, layout).await;
             write_shutdown(&mut stream, ec, message).await;
             drop(stream);
-            None
+            (ec, None)
         }
     }
 }
@@ -199,179 +315,1155 @@ fn is_wm_running(procs: &System, proc_name: &str, wm: &str) -> bool {
     false
 }
 
+/// returns the first window manager named in `desktops`, a ":" separated list in the format of
+/// the "XDG_CURRENT_DESKTOP" environment variable.
+fn wm_from_desktop(desktops: &str) -> Option<WindowManager> {
+    desktops.split(':').find_map(WindowManager::from_name)
+}
+
+/// returns true if a file matching the glob `pattern` exists.
+fn file_exists(pattern: &str) -> bool {
+    glob::glob(pattern).is_ok_and(|mut paths| paths.any(|path| path.is_ok()))
+}
+
+/// returns the window manager whose ipc socket (or command pipe) exists.
+fn wm_from_sockets() -> Option<WindowManager> {
+    use std::env;
+
+    let cache_dir =
+        env::var("XDG_CACHE_HOME").unwrap_or_else(|_| shellexpand::tilde("~/.cache").to_string());
+    let bspwm_socket = env::var("BSPWM_SOCKET").unwrap_or_else(|_| "/tmp/bspwm*_*_*-socket".into());
+    let leftwm_pipe = xdg::BaseDirectories::with_prefix("leftwm")
+        .ok()
+        .and_then(|run_dir| run_dir.find_runtime_file("command-0.pipe"));
+
+    if file_exists(&format!("{cache_dir}/qtile/qtilesocket.*")) {
+        Some(WindowManager::Qtile)
+    } else if file_exists(&bspwm_socket) {
+        Some(WindowManager::Bspwm)
+    } else if leftwm_pipe.is_some() {
+        Some(WindowManager::LeftWM)
+    } else if env::var("SWAYSOCK").is_ok_and(|socket| file_exists(&socket)) {
+        Some(WindowManager::Sway)
+    } else if env::var("I3SOCK").is_ok_and(|socket| file_exists(&socket)) {
+        Some(WindowManager::I3)
+    } else if hyprland::socket_dir().is_some() {
+        Some(WindowManager::Hyprland)
+    } else {
+        None
+    }
+}
+
+/// detects the running window manager by its process, then by "XDG_CURRENT_DESKTOP", and then
+/// by the sockets it listens on.
 pub fn get_running_wm() -> WindowManager {
     use std::env;
-    // use std::path::Path;
-    // println!("[ERROR] Couldn't find the leftwm command.pipe file.");
     let procs = System::new_all();
-    if env::var("DISPLAY").is_err() {
-        WindowManager::NoWM
-    }
-    // if Path::new(&qtile_soc_fname).exists() {
-    else if is_wm_running(&procs, "qtile", "qtile")
+
+    let wm = if is_wm_running(&procs, "qtile", "qtile")
         || is_wm_running(&procs, ".qtile-wrapped", "python")
     {
-        info!("Running in Qtile mode");
-        WindowManager::Qtile
-    // } else if Path::new("/tmp/bspwm_0_0-socket").exists() {
+        Some(WindowManager::Qtile)
     } else if is_wm_running(&procs, "bspwm", "bspwm") {
-        info!("Running in BSPWM mode");
-        WindowManager::Bspwm
-    // } else if leftwm::get_cmd_file() != None {
+        Some(WindowManager::Bspwm)
     } else if is_wm_running(&procs, "leftwm", "leftwm") {
-        info!("Running in leftwm mode");
-        WindowManager::LeftWM
+        Some(WindowManager::LeftWM)
+    } else if is_wm_running(&procs, "sway", "sway") {
+        Some(WindowManager::Sway)
+    } else if is_wm_running(&procs, "i3", "i3") {
+        Some(WindowManager::I3)
+    } else if is_wm_running(&procs, "herbstluftwm", "herbstluftwm") {
+        Some(WindowManager::Herbstluftwm)
+    } else if is_wm_running(&procs, "Hyprland", "Hyprland") {
+        Some(WindowManager::Hyprland)
     } else {
-        info!("Running in headless mode");
-        WindowManager::Headless
+        env::var("XDG_CURRENT_DESKTOP")
+            .ok()
+            .and_then(|desktops| wm_from_desktop(&desktops))
+            .or_else(wm_from_sockets)
+    };
+
+    match wm {
+        Some(wm) => {
+            info!("Running in {} mode", wm.name());
+            wm
+        }
+        None if env::var("DISPLAY").is_err() => {
+            info!("no display found, running in headless mode");
+            WindowManager::NoWM
+        }
+        #[cfg(feature = "ewmh")]
+        None if ewmh::is_supported() => {
+            info!("Running in ewmh mode");
+            WindowManager::Ewmh
+        }
+        None => {
+            info!("Running in headless mode");
+            WindowManager::Headless
+        }
+    }
+}
+
+/// returns the window manager to use for the `wm` setting. "auto" detects the running one.
+pub fn detect_wm(setting: &str) -> WindowManager {
+    match setting {
+        "auto" => get_running_wm(),
+        "none" => {
+            info!("running in headless mode (set in the config)");
+            WindowManager::Headless
+        }
+        name => match WindowManager::from_name(name) {
+            Some(wm) => {
+                info!("Running in {} mode (set in the config)", wm.name());
+                wm
+            }
+            None => {
+                error!("unknown window manager \"{name}\", detecting the running one instead");
+                get_running_wm()
+            }
+        },
     }
 }
 
-async fn recv_loop(configs: config::Config) -> std::io::Result<()> {
+#[test]
+fn test_wm_from_desktop() {
+    assert_eq!(wm_from_desktop("LeftWM"), Some(WindowManager::LeftWM));
+    assert_eq!(wm_from_desktop("GNOME:bspwm"), Some(WindowManager::Bspwm));
+    assert_eq!(wm_from_desktop("sway"), Some(WindowManager::Sway));
+    assert_eq!(wm_from_desktop("XFCE"), None);
+}
+
+/// a running task and the name of the config section that turned it on. (eg. "usb")
+type NamedTask = (&'static str, task::JoinHandle<()>);
+
+/// starts the hook runner and the event sources that are turned on in the config, the window
+/// manager's events are watched for `wm`. only the tasks `start` gives true for (by name) are
+/// started.
+#[cfg(feature = "hooks")]
+fn start_event_tasks(
+    configs: &config::Config,
+    #[cfg_attr(
+        not(any(feature = "bspwm", feature = "hyprland")),
+        allow(unused_variables)
+    )]
+    wm: WindowManager,
+    start: &dyn Fn(&str) -> bool,
+    event_bus: &events::EventTx,
+    actions: mpsc::Sender<String>,
+) -> Vec<NamedTask> {
+    let mut tasks = Vec::new();
+
+    if start("hooks") && Some(true) == configs.hooks.listen {
+        let conf_hooks = configs.hooks.hooks.clone();
+        let mut event_rx = event_bus.subscribe();
+        tasks.push((
+            "hooks",
+            task::spawn(async move {
+                hooks::check_event_hooks(&mut event_rx, conf_hooks).await;
+            }),
+        ));
+    }
+
+    if start("monitors") && configs.monitors.watch.unwrap_or(false) {
+        tasks.push((
+            "monitors",
+            task::spawn(events::monitor::watch(
+                PathBuf::from(events::monitor::DRM_DIR),
+                configs.monitors.clone(),
+                event_bus.clone(),
+                actions,
+            )),
+        ));
+    }
+
+    if start("usb") && configs.usb.watch.unwrap_or(false) {
+        tasks.push(("usb", task::spawn(events::usb::watch(event_bus.clone()))));
+    }
+
+    if start("network") && configs.network.watch.unwrap_or(false) {
+        tasks.push((
+            "network",
+            task::spawn(events::network::watch(
+                PathBuf::from(events::network::PROC_ROUTE),
+                PathBuf::from(events::network::SYS_NET_DIR),
+                event_bus.clone(),
+            )),
+        ));
+    }
+
+    if start("power_supply") && configs.power_supply.watch.unwrap_or(false) {
+        tasks.push((
+            "power_supply",
+            task::spawn(events::power_supply::watch(
+                PathBuf::from(common::battery::POWER_SUPPLY_DIR),
+                configs
+                    .power_supply
+                    .thresholds
+                    .clone()
+                    .unwrap_or_else(|| vec![15, 5]),
+                event_bus.clone(),
+            )),
+        ));
+    }
+
+    if start("port_sentinel") && configs.port_sentinel.watch.unwrap_or(false) {
+        let port_pipe = config::get_pipe_f();
+        let _ = std::fs::remove_file(&port_pipe);
+        match bind_socket(&port_pipe, configs.shares_sockets(config::current_uid())) {
+            Ok(listener) => tasks.push((
+                "port_sentinel",
+                task::spawn(events::ports::watch(
+                    listener,
+                    configs.port_sentinel.uid,
+                    event_bus.clone(),
+                )),
+            )),
+            Err(e) => {
+                error!("could not listen for port-sentinel on {port_pipe:?}. got error: \"{e}\"")
+            }
+        }
+    }
+
+    #[cfg(feature = "bluetooth")]
+    if start("bluetooth") && configs.bluetooth.watch.unwrap_or(false) {
+        tasks.push((
+            "bluetooth",
+            task::spawn(events::bluetooth::watch(event_bus.clone())),
+        ));
+    }
+
+    #[cfg(feature = "bspwm")]
+    if start("wm_events") && configs.wm_events.watch.unwrap_or(false) && wm == WindowManager::Bspwm
+    {
+        tasks.push((
+            "wm_events",
+            task::spawn(events::bspwm::watch(
+                event_bus.clone(),
+                configs.server.wm_socket.clone(),
+            )),
+        ));
+    }
+
+    #[cfg(feature = "hyprland")]
+    if start("wm_events")
+        && configs.wm_events.watch.unwrap_or(false)
+        && wm == WindowManager::Hyprland
+    {
+        tasks.push((
+            "wm_events",
+            task::spawn(events::hyprland::watch(event_bus.clone())),
+        ));
+    }
+
+    tasks
+}
+
+/// polls the config file and asks for it to be reloaded when it changes.
+async fn watch_config(path: PathBuf, actions: mpsc::Sender<String>) {
+    info!("watching {path:?} for changes");
+    let modified = |path: &Path| {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+    let mut last_modified = modified(&path);
+
+    loop {
+        sleep(CONFIG_POLL_TIME).await;
+        let now_modified = modified(&path);
+
+        if now_modified != last_modified {
+            last_modified = now_modified;
+            info!("the config file changed");
+            if actions.send("reload-config".to_string()).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// the channels tasks use to reach `recv_loop`.
+struct LoopTx {
+    /// internal commands, like "reload-config".
+    actions: mpsc::Sender<String>,
+    /// connections to the `[[security.sockets]]`, with the socket's path.
+    conns: mpsc::Sender<(UnixStream, String)>,
+}
+
+/// accepts connections on one of the `[[security.sockets]]` and passes them on to `recv_loop`
+/// along with the socket's path.
+async fn accept_on(
+    listener: UnixListener,
+    path: String,
+    conns: mpsc::Sender<(UnixStream, String)>,
+) {
+    info!("listening on socket: {path}");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _addr)) => {
+                if conns.send((stream, path.clone())).await.is_err() {
+                    break;
+                }
+            }
+            Err(e) => error!("could not accept a connection on \"{path}\". got error: \"{e}\""),
+        }
+    }
+}
+
+/// starts the tasks the server needs for itself (rather than for events) that are turned on in
+/// the config. only the tasks `start` gives true for (by name) are started.
+fn start_server_tasks(
+    configs: &config::Config,
+    start: &dyn Fn(&str) -> bool,
+    tx: &LoopTx,
+) -> Vec<NamedTask> {
+    let mut tasks = Vec::new();
+
+    if start("config") && configs.server.watch_config.unwrap_or(false) {
+        tasks.push((
+            "config",
+            task::spawn(watch_config(
+                PathBuf::from(config::config_file()),
+                tx.actions.clone(),
+            )),
+        ));
+    }
+
+    let sockets = if start("socket") {
+        configs.security.sockets.as_slice()
+    } else {
+        &[]
+    };
+    for socket in sockets {
+        remove_socket(&socket.path);
+        match bind_socket(&socket.path, configs.shares_sockets(config::current_uid())) {
+            Ok(listener) => tasks.push((
+                "socket",
+                task::spawn(accept_on(listener, socket.path.clone(), tx.conns.clone())),
+            )),
+            Err(e) => error!(
+                "could not listen on \"{}\". got error: \"{e}\"",
+                socket.path
+            ),
+        }
+    }
+
+    tasks
+}
+
+/// starts every task the config turns on that `start` gives true for (by name). `wm` is the
+/// window manager the server is using.
+fn start_tasks(
+    configs: &config::Config,
+    #[cfg(feature = "hooks")] wm: WindowManager,
+    start: &dyn Fn(&str) -> bool,
+    #[cfg(feature = "hooks")] event_bus: &events::EventTx,
+    tx: &LoopTx,
+) -> Vec<NamedTask> {
+    #[cfg_attr(not(feature = "hooks"), allow(unused_mut))]
+    let mut tasks = start_server_tasks(configs, start, tx);
+    #[cfg(feature = "hooks")]
+    tasks.extend(start_event_tasks(
+        configs,
+        wm,
+        start,
+        event_bus,
+        tx.actions.clone(),
+    ));
+    tasks
+}
+
+/// stops the "wm_events" task and starts it again for `wm`, when the window manager changed
+/// without the config changing.
+#[cfg(feature = "hooks")]
+fn restart_wm_events(
+    tasks: &mut Vec<NamedTask>,
+    configs: &config::Config,
+    wm: WindowManager,
+    event_bus: &events::EventTx,
+    tx: &LoopTx,
+) {
+    tasks.retain(|(name, task)| {
+        if *name == "wm_events" {
+            task.abort();
+        }
+        *name != "wm_events"
+    });
+    tasks.extend(start_tasks(
+        configs,
+        wm,
+        &|name| name == "wm_events",
+        event_bus,
+        tx,
+    ));
+}
+
+/// returns true if the part of the config that the task `name` is started from differs between
+/// `old` and `new`, so the task has to be restarted when the config is reloaded.
+fn task_changed(name: &str, old: &config::Config, new: &config::Config) -> bool {
+    let own_uid = config::current_uid();
+    let sharing_changed = old.shares_sockets(own_uid) != new.shares_sockets(own_uid);
+
+    match name {
+        "config" => old.server.watch_config != new.server.watch_config,
+        "socket" => old.security.sockets != new.security.sockets || sharing_changed,
+        "hooks" => old.hooks != new.hooks,
+        "monitors" => old.monitors != new.monitors,
+        "usb" => old.usb != new.usb,
+        "network" => old.network != new.network,
+        "power_supply" => old.power_supply != new.power_supply,
+        "port_sentinel" => old.port_sentinel != new.port_sentinel || sharing_changed,
+        "bluetooth" => old.bluetooth != new.bluetooth,
+        "wm_events" => {
+            old.wm_events != new.wm_events || old.server.wm_socket != new.server.wm_socket
+        }
+        _ => true,
+    }
+}
+
+#[test]
+fn test_task_changed() {
+    let old: config::Config = toml::de::from_str(
+        "[server]\nlisten_socket = \"/tmp/a.sock\"\nwm_socket = \"/tmp/wm.sock\"\n\
+         [usb]\nwatch = true\n[power_supply]\nwatch = true\nthresholds = [15, 5]\n",
+    )
+    .unwrap();
+    let mut new = old.clone();
+    new.server.listen_socket = "/tmp/b.sock".to_string();
+    new.power_supply.thresholds = Some(vec![20, 5]);
+
+    assert!(!task_changed("usb", &old, &new));
+    assert!(task_changed("power_supply", &old, &new));
+    assert!(!task_changed("wm_events", &old, &new));
+}
+
+/// reload-config command. the new config is checked (and the new listen socket bound) before
+/// anything changes, so a bad config leaves the server as it was. `wm_override` is the `--wm`
+/// flag the server was started with.
+async fn reload_config(
+    configs: &mut config::Config,
+    wm_override: &Option<String>,
+    listener: &mut UnixListener,
+    wm: &mut WindowManager,
+    tasks: &mut Vec<NamedTask>,
+    #[cfg(feature = "hooks")] event_bus: &events::EventTx,
+    tx: &LoopTx,
+) -> GenericRes {
+    let mut new_configs = match config::get_configs() {
+        Ok(new_configs) => new_configs,
+        Err(e) => {
+            error!("could not reload configs. reason: \"{e}\"");
+            return (4, Some(e));
+        }
+    };
+    if wm_override.is_some() {
+        new_configs.server.wm = wm_override.clone();
+    }
+
+    let shared = new_configs.shares_sockets(config::current_uid());
+    let old_socket = configs.server.listen_socket.clone();
+    let moved = new_configs.server.listen_socket != old_socket;
+    if moved {
+        remove_socket(&new_configs.server.listen_socket);
+        match bind_socket(&new_configs.server.listen_socket, shared) {
+            Ok(new_listener) => *listener = new_listener,
+            Err(e) => {
+                error!("could not listen on the new socket. reason: \"{e}\"");
+                return (
+                    4,
+                    Some(format!(
+                        "could not listen on \"{}\": {e}",
+                        new_configs.server.listen_socket
+                    )),
+                );
+            }
+        }
+        info!(
+            "now listening on socket: {}",
+            new_configs.server.listen_socket
+        );
+    } else if let Err(e) = share_socket(&old_socket, shared) {
+        error!("could not change who can use \"{old_socket}\". got error: \"{e}\"");
+    }
+
+    let old_wm = *wm;
+    if new_configs.server.wm != configs.server.wm {
+        *wm = detect_wm(new_configs.server.wm.as_deref().unwrap_or("auto"));
+    }
+
+    // tasks whose part of the config didn't change keep running, so they don't lose what they
+    // were watching.
+    let changed = |name: &str| {
+        task_changed(name, configs, &new_configs) || (name == "wm_events" && *wm != old_wm)
+    };
+    let (old_tasks, kept): (Vec<NamedTask>, Vec<NamedTask>) =
+        tasks.drain(..).partition(|(name, _)| changed(name));
+    for (_, old_task) in old_tasks {
+        old_task.abort();
+    }
+    if changed("socket") {
+        clear_security_sockets(configs);
+    }
+    *tasks = kept;
+    tasks.extend(start_tasks(
+        &new_configs,
+        #[cfg(feature = "hooks")]
+        *wm,
+        &changed,
+        #[cfg(feature = "hooks")]
+        event_bus,
+        tx,
+    ));
+    *configs = new_configs;
+
+    // the old socket goes last, once the new config is in use.
+    if moved {
+        remove_socket(&old_socket);
+    }
+
+    info!("config reloaded");
+    (0, Some("config reloaded".to_string()))
+}
+
+/// what the server is doing right now, reported by the "status" command.
+struct Status<'a> {
+    wm: &'a WindowManager,
+    wm_setting: &'a str,
+    wm_socket: &'a str,
+    started: Instant,
+    loaded_layout: &'a Option<String>,
+    layout: &'a qtile::QtileCmdData,
+    event_tasks: &'a [NamedTask],
+}
+
+impl Status<'_> {
+    /// status command. responds with the status as json.
+    fn report(&self) -> GenericRes {
+        let event_sources: Vec<&str> = self
+            .event_tasks
+            .iter()
+            .filter(|(_, event_task)| !event_task.is_finished())
+            .map(|(name, _)| *name)
+            .collect();
+
+        // port-sentinel reports to the port_sentinel event source, which needs hooks.
+        #[cfg(feature = "hooks")]
+        let port_sentinel = events::ports::last_report().map(|ago| ago.as_secs());
+        #[cfg(not(feature = "hooks"))]
+        let port_sentinel: Option<u64> = None;
+
+        let mut status = serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime": self.started.elapsed().as_secs(),
+            "wm": self.wm.name(),
+            "wm_setting": self.wm_setting,
+            "wm_socket": self.wm_socket,
+            "layout": self.loaded_layout,
+            "event_sources": event_sources,
+            "port_sentinel": port_sentinel,
+        });
+
+        if *self.wm == WindowManager::Qtile {
+            status["qtile"] = serde_json::json!(self.layout);
+        }
+
+        (0, Some(status.to_string()))
+    }
+}
+
+/// writes every event that matches `filters` to the client as a line of json. the usual response
+/// header (error code 0) goes first so the client knows the subscription worked. a client that
+/// went away is noticed the next time an event can't be written to it.
+#[cfg(feature = "hooks")]
+async fn stream_events(mut stream: UnixStream, mut events: events::EventRx, filters: Vec<String>) {
+    info!("client subscribed to events: {filters:?}");
+
+    if let Err(e) = stream.write_all(&make_payload(0, None)).await {
+        error!("could not write out to subscriber because: \"{e}\"");
+        return;
+    }
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(n)) => {
+                warn!("a subscriber fell behind, {n} events were not sent to it");
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        if !event.matches(&filters) {
+            continue;
+        }
+
+        let mut line = match serde_json::to_string(&event) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("could not serialize event {event:?}. got error: \"{e}\"");
+                continue;
+            }
+        };
+        line.push('\n');
+
+        if let Err(e) = stream.write_all(line.as_bytes()).await {
+            debug!("subscriber went away. \"{e}\"");
+            break;
+        }
+    }
+
+    let _ = stream.shutdown().await;
+    info!("client unsubscribed from events");
+}
+
+/// returns the event caused by a client command that succeeded, if it causes one. these let
+/// subscribers (eg. status bars) follow changes without polling the system.
+#[cfg(feature = "hooks")]
+fn command_event(cmd: &str, args: &str) -> Option<events::Event> {
+    match cmd {
+        "vol-up" | "vol-down" | "mute" => {
+            let (level, muted) = common::media::get_volume()?;
+            Some(
+                events::Event::new("volume-changed")
+                    .with("level", &level.to_string())
+                    .with("muted", &muted.to_string()),
+            )
+        }
+        "inc-bl" | "dec-bl" => Some(
+            events::Event::new("brightness-changed")
+                .with("level", &common::backlight::get_bright()?.to_string()),
+        ),
+        "focus-on" => Some(events::Event::new("desktop-focused").with("desktop", args.trim())),
+        "load-layout" => Some(events::Event::new("layout-loaded").with("layout", args.trim())),
+        _ => None,
+    }
+}
+
+/// runs a command that came from inside the server (eg. from an event source) rather than
+/// from a client.
+async fn run_action(
+    action: &str,
+    wm: &WindowManager,
+    spath: &str,
+    layout: &mut qtile::QtileCmdData,
+    loaded_layout: &Option<String>,
+) {
+    let (cmd, mut args) = split_cmd(action);
+
+    if cmd == "apply-workspaces" && args.is_empty() {
+        match loaded_layout {
+            Some(layout_name) => args = layout_name.clone(),
+            None => {
+                debug!("no layout loaded, no workspaces to apply");
+                return;
+            }
+        }
+    }
+
+    let (ec, message) = switch_board(wm, &cmd, &args, spath, layout).await;
+    if ec > 0 {
+        error!(
+            "internal action \"{cmd} {args}\" failed with error code {ec}. message: {message:?}"
+        );
+    }
+}
+
+async fn recv_loop(
+    configs: &mut config::Config,
+    wm_override: Option<String>,
+) -> std::io::Result<()> {
     // println!("recv_loop");
-    let program_socket = configs.server.listen_socket.as_str();
-    let wm_socket = configs.server.wm_socket.as_str();
+    if wm_override.is_some() {
+        configs.server.wm = wm_override.clone();
+    }
 
-    info!("listening on socket: {}", program_socket);
+    info!("listening on socket: {}", configs.server.listen_socket);
 
-    let listener = UnixListener::bind(program_socket)?;
+    let mut listener = bind_socket(
+        &configs.server.listen_socket,
+        configs.shares_sockets(config::current_uid()),
+    )?;
+    let started = Instant::now();
     let mut layout: qtile::QtileCmdData = qtile::QtileCmdData::new();
+    // the name of the last layout that loaded without errors.
+    let mut loaded_layout: Option<String> = None;
+    let (actions, mut action_rx) = mpsc::channel::<String>(8);
+    let (conns, mut conn_rx) = mpsc::channel::<(UnixStream, String)>(8);
+    let tx = LoopTx { actions, conns };
 
-    // let (mut hooks, hook_checking) =
-    //     if Some(true) == configs.hooks.listen && cfg!(feature = "hooks") {
-    //         let (control_tx, mut control_rx) = tokio::sync::mpsc::channel::<hooks::HookDB>(1);
-    //         let (cmd_tx, mut cmd_rx) = tokio::sync::mpsc::channel::<msgs::EventCmd>(1);
-    //         let stop_exec = configs.hooks.exec_ignore.clone();
-    //         let conf_hooks = configs.hooks.hooks.clone();
-    //         // make a runtime dir for auto-desk
-    //         let _ = create_dir(config::get_pipe_d());
-    //         let hook_checking = task::spawn(async move {
-    //             hooks::check_even_hooks(
-    //                 &mut control_rx,
-    //                 &mut cmd_rx,
-    //                 stop_exec,
-    //                 conf_hooks,
-    //                 configs.hooks.ignore_web,
-    //             )
-    //             .await;
-    //         });
-    //         let hooks_db = hooks::HookDB::new();
-    //         (
-    //             Some(hooks::HookData {
-    //                 send: control_tx,
-    //                 cmd: cmd_tx,
-    //                 db: hooks_db,
-    //             }),
-    //             Some(hook_checking),
-    //         )
-    //     } else {
-    //         (None, None)
-    //     };
-
-    let wm = get_running_wm();
+    #[cfg(feature = "hooks")]
+    let event_bus = events::new_bus();
+    let mut wm = detect_wm(configs.server.wm.as_deref().unwrap_or("auto"));
+    let mut event_tasks = start_tasks(
+        configs,
+        #[cfg(feature = "hooks")]
+        wm,
+        &|_| true,
+        #[cfg(feature = "hooks")]
+        &event_bus,
+        &tx,
+    );
+    #[cfg(feature = "hooks")]
+    let mut subscribers: Vec<task::JoinHandle<()>> = Vec::new();
 
     loop {
-        match listener.accept().await {
-            Ok((mut stream, _addr)) => {
-                /* connection succeeded */
-                let command = read_command(&mut stream).await;
-                debug!("command: {}", command);
-                let (cmd, args) = split_cmd(&command);
-                if cmd == "SERVER-EXIT" {
+        // these can change when the config is reloaded.
+        let program_socket = configs.server.listen_socket.clone();
+        let wm_socket = configs.server.wm_socket.clone();
+        let wm_setting = configs
+            .server
+            .wm
+            .clone()
+            .unwrap_or_else(|| "auto".to_string());
+
+        let (mut stream, socket) = tokio::select! {
+            conn = listener.accept() => match conn {
+                Ok((stream, _addr)) => (stream, None),
+                Err(err) => {
+                    error!("could not except socket connection. {:#?}", err);
+                    /* connection failed */
                     break;
                 }
+            },
+            Some((stream, socket)) = conn_rx.recv() => (stream, Some(socket)),
+            Some(action) = action_rx.recv() => {
+                if action == "reload-config" {
+                    reload_config(
+                        configs,
+                        &wm_override,
+                        &mut listener,
+                        &mut wm,
+                        &mut event_tasks,
+                        #[cfg(feature = "hooks")]
+                        &event_bus,
+                        &tx,
+                    )
+                    .await;
+                } else {
+                    run_action(&action, &wm, &wm_socket, &mut layout, &loaded_layout).await;
+                }
+                continue;
+            }
+        };
+
+        /* connection succeeded */
+        let peer = match connecting_peer(&configs.security, &stream) {
+            Ok(peer) => peer,
+            Err(message) => {
+                write_shutdown(&mut stream, 11, Some(message)).await;
+                continue;
+            }
+        };
+        let Some(command) = read_command(&mut stream).await else {
+            continue;
+        };
+        debug!("command: {}", command);
+        let (cmd, args) = split_cmd(&command);
+        if let Err(message) = check_peer(&configs.security, socket.as_deref(), &peer, &cmd) {
+            write_shutdown(&mut stream, 11, Some(message)).await;
+            continue;
+        }
+        if cmd == "SERVER-EXIT" {
+            break;
+        }
+        if cmd == SUBSCRIBE_CMD {
+            #[cfg(feature = "hooks")]
+            {
+                subscribers.retain(|subscriber| !subscriber.is_finished());
+                subscribers.push(task::spawn(stream_events(
+                    stream,
+                    event_bus.subscribe(),
+                    events::parse_filters(&args),
+                )));
+            }
+            #[cfg(not(feature = "hooks"))]
+            write_shutdown(
+                &mut stream,
+                1,
+                Some("subscribing to events needs the \"hooks\" feature".to_string()),
+            )
+            .await;
+            continue;
+        }
+        if cmd == "reload-config" {
+            let (ec, message) = reload_config(
+                configs,
+                &wm_override,
+                &mut listener,
+                &mut wm,
+                &mut event_tasks,
+                #[cfg(feature = "hooks")]
+                &event_bus,
+                &tx,
+            )
+            .await;
+            write_shutdown(&mut stream, ec, message).await;
+            continue;
+        }
+        if cmd == "rescan-wm" {
+            let new_wm = detect_wm(&wm_setting);
+            if new_wm != wm {
+                wm = new_wm;
+                #[cfg(feature = "hooks")]
+                restart_wm_events(&mut event_tasks, configs, wm, &event_bus, &tx);
+            }
+            write_shutdown(&mut stream, 0, Some(wm.name().to_string())).await;
+            continue;
+        }
+        // the window manager may have started after the server did.
+        if wm_setting == "auto"
+            && matches!(wm, WindowManager::Headless | WindowManager::NoWM)
+            && commands::needs_wm(&cmd)
+        {
+            debug!("{cmd} needs a window manager, looking for one again");
+            wm = get_running_wm();
+            #[cfg(feature = "hooks")]
+            if !matches!(wm, WindowManager::Headless | WindowManager::NoWM) {
+                restart_wm_events(&mut event_tasks, configs, wm, &event_bus, &tx);
+            }
+        }
+        if cmd == "status" {
+            let status = Status {
+                wm: &wm,
+                wm_setting: &wm_setting,
+                wm_socket: &wm_socket,
+                started,
+                loaded_layout: &loaded_layout,
+                layout: &layout,
+                event_tasks: &event_tasks,
+            };
+            let (ec, message) = status.report();
+            write_shutdown(&mut stream, ec, message).await;
+            continue;
+        }
+        let layout_name = (cmd == "load-layout").then(|| args.clone());
 
-                match wm {
-                    WindowManager::Qtile => {
-                        if let Some(lo) = handle_client_qtile(
-                            cmd,
-                            args,
-                            &wm,
-                            stream,
-                            &mut layout,
-                            // &mut hooks,
-                            program_socket,
-                        )
-                        .await
-                        {
-                            layout = lo.clone();
-                            debug!("layout: {:?}", lo);
-                            task::spawn(async move {
-                                for program in lo.queue {
-                                    common::open_program(&program);
-                                }
-                            });
-                        }
-                    }
-                    WindowManager::Bspwm
-                    | WindowManager::LeftWM
-                    | WindowManager::Headless
-                    | WindowManager::NoWM => {
-                        handle_client_gen(
-                            cmd,
-                            args,
-                            &wm,
-                            // &mut hooks,
-                            stream,
-                            wm_socket,
-                            &mut layout,
-                        )
-                        .await;
-                    }
+        let ec = match wm {
+            WindowManager::Qtile => {
+                let (ec, new_layout) = handle_client_qtile(
+                    cmd.clone(),
+                    args.clone(),
+                    &wm,
+                    stream,
+                    &mut layout,
+                    &program_socket,
+                )
+                .await;
+                if let Some(lo) = new_layout {
+                    let socket = qtile::get_socket();
+                    layout = match socket {
+                        Some(_) => lo.handled(),
+                        None => lo.clone(),
+                    };
+                    loaded_layout = layout_name;
+                    debug!("layout: {:?}", lo);
+                    task::spawn_blocking(move || qtile::launch_layout(socket, lo));
                 }
+                ec
             }
-            Err(err) => {
-                error!("could not except socket connection. {:#?}", err);
-                /* connection failed */
-                break;
+            WindowManager::Bspwm
+            | WindowManager::LeftWM
+            | WindowManager::I3
+            | WindowManager::Sway
+            | WindowManager::Herbstluftwm
+            | WindowManager::Ewmh
+            | WindowManager::Hyprland
+            | WindowManager::Mock
+            | WindowManager::Headless
+            | WindowManager::NoWM => {
+                let ec = handle_client_gen(
+                    cmd.clone(),
+                    args.clone(),
+                    &wm,
+                    stream,
+                    &wm_socket,
+                    &mut layout,
+                )
+                .await;
+                if ec == 0 && layout_name.is_some() {
+                    loaded_layout = layout_name;
+                }
+                ec
+            }
+        };
+
+        #[cfg(feature = "hooks")]
+        if ec == 0 {
+            if let Some(event) = command_event(&cmd, &args) {
+                events::publish(&event_bus, event);
             }
         }
+        #[cfg(not(feature = "hooks"))]
+        let _ = ec;
     }
 
     info!("killing unix socket");
     drop(listener);
     info!("unix socket killed");
-    // info!("stopping event listeners");
-    // if let Some(h_dat) = hooks {
-    //     let _ = h_dat.cmd.send(msgs::EventCmd::Exit).await;
-    //     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-    // }
-    // if let Some(hook_checking) = hook_checking {
-    //     hook_checking.abort()
-    // }
-    // info!("event listeners stopped");
+    info!("stopping event listeners");
+    for (_, event_task) in event_tasks {
+        event_task.abort();
+    }
+    #[cfg(feature = "hooks")]
+    for subscriber in subscribers {
+        subscriber.abort();
+    }
+    info!("event listeners stopped");
     Ok(())
 }
 
 fn clear_sockets(prog_so: &str) {
     for p in [prog_so, &config::get_pipe_f()] {
-        let path = std::path::Path::new(&p);
-        if path.exists() {
-            info!("clearing socker file at {:?}", path);
-            if let Err(e) = std::fs::remove_file(path) {
-                error!("deleting previous socket at \"{path:?}\" returned error: \"{e}\"");
-            }
+        remove_socket(p);
+    }
+}
+
+/// removes the `[[security.sockets]]` of `configs`.
+fn clear_security_sockets(configs: &config::Config) {
+    for socket in &configs.security.sockets {
+        remove_socket(&socket.path);
+    }
+}
+
+fn remove_socket(socket: &str) {
+    let path = Path::new(socket);
+    if path.exists() {
+        info!("clearing socker file at {:?}", path);
+        if let Err(e) = std::fs::remove_file(path) {
+            error!("deleting previous socket at \"{path:?}\" returned error: \"{e}\"");
+        }
+    }
+}
+
+/// listens on `socket`. the dir it goes in is made if it's missing, and when that dir is the
+/// runtime dir it has to belong to us. (see `share_socket` for who can use it)
+fn bind_socket(socket: &str, shared: bool) -> std::io::Result<UnixListener> {
+    use std::fs::{metadata, DirBuilder};
+    use std::io::{Error, ErrorKind};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    if let Some(dir) = Path::new(socket).parent() {
+        if !dir.exists() {
+            DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        }
+
+        if dir == Path::new(&config::get_runtime_d())
+            && metadata(dir)?.uid() != config::current_uid()
+        {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("the runtime dir {dir:?} belongs to another user"),
+            ));
+        }
+    }
+
+    let listener = UnixListener::bind(socket)?;
+    share_socket(socket, shared)?;
+    Ok(listener)
+}
+
+/// sets who can use `socket`. only its owner can (0600, and the runtime dir is kept private,
+/// 0700) unless it's `shared` with the other users the config names. then anyone may connect
+/// (0666, and the runtime dir is 0711 so it can be reached but not listed) and the server checks
+/// who they are.
+fn share_socket(socket: &str, shared: bool) -> std::io::Result<()> {
+    use std::fs::{metadata, set_permissions, Permissions};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let (dir_mode, socket_mode) = if shared {
+        (0o711, 0o666)
+    } else {
+        (0o700, 0o600)
+    };
+
+    if let Some(dir) = Path::new(socket).parent() {
+        if dir == Path::new(&config::get_runtime_d()) {
+            set_permissions(dir, Permissions::from_mode(dir_mode))?;
+        }
+
+        // eg. "$XDG_RUNTIME_DIR" itself is private, so other users can't get past it.
+        let private = dir
+            .ancestors()
+            .find(|dir| metadata(dir).is_ok_and(|meta| meta.mode() & 0o001 == 0));
+        if let (true, Some(private)) = (shared, private) {
+            warn!("other users can't reach {socket:?} because {private:?} is private");
         }
     }
+
+    set_permissions(socket, Permissions::from_mode(socket_mode))
+}
+
+#[tokio::test]
+async fn test_share_socket() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let socket = std::env::temp_dir().join(format!("auto-desk-share-{}", std::process::id()));
+    let socket = socket.to_str().unwrap();
+    let mode = || std::fs::metadata(socket).unwrap().permissions().mode() & 0o777;
+
+    let _listener = bind_socket(socket, false).unwrap();
+    assert_eq!(mode(), 0o600);
+    share_socket(socket, true).unwrap();
+    assert_eq!(mode(), 0o666);
+
+    let _ = std::fs::remove_file(socket);
+}
+
+/// gets the process on the other end of `stream` and checks that its user may send commands,
+/// before anything is read from it.
+fn connecting_peer(
+    security: &config::Security,
+    stream: &UnixStream,
+) -> Result<security::Peer, String> {
+    let peer = match security::Peer::of(stream) {
+        Ok(peer) => peer,
+        Err(e) => {
+            error!("could not get the credentials of a client. got error: \"{e}\"");
+            return Err("could not get the client's credentials".to_string());
+        }
+    };
+
+    security::check_uid(security, &peer, config::current_uid()).inspect_err(|e| {
+        warn!(
+            "refused a connection from uid {} ({:?}). reason: \"{e}\"",
+            peer.uid, peer.exe
+        )
+    })?;
+
+    Ok(peer)
 }
 
-pub async fn server_start() {
-    let configs = match config::get_configs() {
+/// checks the `[security]` policies for `peer`, which sent `cmd` over `socket` (None for the
+/// listen socket).
+fn check_peer(
+    security: &config::Security,
+    socket: Option<&str>,
+    peer: &security::Peer,
+    cmd: &str,
+) -> Result<(), String> {
+    security::check(security, socket, peer, config::current_uid(), cmd).inspect_err(|e| {
+        warn!(
+            "refused \"{cmd}\" from uid {} ({:?}). reason: \"{e}\"",
+            peer.uid, peer.exe
+        )
+    })
+}
+
+/// starts the server. `wm` overrides the `wm` setting in the config. returns 4 if the config
+/// can't be loaded.
+pub async fn server_start(wm: Option<String>) -> u8 {
+    let mut configs = match config::get_configs() {
         Ok(configs) => configs,
         Err(e) => {
             error!("could not load configs. reason: \"{e}\"");
             info!("now exiting because of previous error.");
-            return;
+            return 4;
         }
     };
-    let prog_so = configs.server.listen_socket.clone();
-    clear_sockets(&prog_so);
+    clear_sockets(&configs.server.listen_socket);
 
-    match recv_loop(configs).await {
+    match recv_loop(&mut configs, wm).await {
         Ok(_) => {}
         Err(e) => error!("recv_loop exited with error: \"{}\"", e),
     }
 
-    clear_sockets(&prog_so);
+    // the config may have been reloaded with a new listen socket.
+    clear_sockets(&configs.server.listen_socket);
+    clear_security_sockets(&configs);
     info!("server session terminated");
+    0
+}
+
+/// starts `recv_loop` with the mock window manager on a temp socket, on its own runtime. returns
+/// the runtime (dropping it stops the server) and the socket.
+#[cfg(test)]
+fn mock_server(name: &str) -> (tokio::runtime::Runtime, String) {
+    let socket = std::env::temp_dir()
+        .join(format!("auto-desk-{name}-{}.sock", std::process::id()))
+        .to_string_lossy()
+        .to_string();
+    let mut configs = config::Config {
+        server: config::Server {
+            listen_socket: socket.clone(),
+            wm: Some("mock".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(async move { recv_loop(&mut configs, None).await });
+    while !Path::new(&socket).exists() {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    (runtime, socket)
+}
+
+#[test]
+fn test_mock_wm() {
+    use crate::client::send_data;
+
+    let (_runtime, socket) = mock_server("mock-wm");
+    let send = |command: &str| send_data(command.to_string(), &socket);
+    mock::mock().reset();
+
+    assert_eq!(
+        send("open-at kitty --single-instance web"),
+        (0, String::new())
+    );
+    assert_eq!(send("move-to mail"), (0, String::new()));
+    mock::mock().open_window(4242, "firefox", "web");
+    assert_eq!(
+        send("query-windows"),
+        (0, "mail\tkitty\nweb\tfirefox".to_string())
+    );
+    assert_eq!(send("close-focused").0, 0);
+    assert_eq!(send("close-focused").0, 6);
+    assert_eq!(send("load-layout test-data/test1.yml").0, 0);
+    assert_eq!(send("query-desktops"), (0, "mail\n10\n9\n8\n7".to_string()));
+    assert_eq!(send("load-layout no-such-layout").0, 4);
+    assert_eq!(send("no-such-command").0, 1);
+    assert!(send("status")
+        .1
+        .contains("\"layout\":\"test-data/test1.yml\""));
+
+    let wm = mock::mock();
+    let windows: Vec<(u32, &str, &str)> = wm
+        .windows
+        .iter()
+        .map(|window| (window.pid, window.class.as_str(), window.desktop.as_str()))
+        .collect();
+    assert_eq!(
+        windows,
+        [
+            (10000, "kitty", "mail"),
+            (10002, "kitty", "10"),
+            (10003, "obsidian", "9"),
+            (10004, "pavucontrol", "8"),
+            (10005, "kitty", "8"),
+            (10006, "kitty", "8"),
+            (10007, "brave", "7"),
+        ]
+    );
+    assert_eq!(wm.workspaces, [(0, 1), (1, 0), (2, 2)].into());
+    let calls: Vec<&str> = wm.calls.iter().map(|(cmd, _)| cmd.as_str()).collect();
+    assert_eq!(
+        calls,
+        [
+            "open-at",
+            "move-to",
+            "query-windows",
+            "close-focused",
+            "close-focused",
+            "load-layout",
+            "query-desktops",
+            "load-layout"
+        ]
+    );
+    drop(wm);
+
+    assert_eq!(send("SERVER-EXIT").0, 8);
+    let _ = std::fs::remove_file(&socket);
 }
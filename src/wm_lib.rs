@@ -2,7 +2,13 @@
 use log::error;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
-use std::{collections::HashMap, fs::read_to_string};
+use std::sync::Arc;
+use std::thread;
+use std::{collections::HashMap, fs::read_dir, fs::read_to_string};
+
+// TODO: pull path from config file
+const LAYOUT_DIR: &str = "~/.config/auto-desk/layouts/";
+const LAYOUT_EXTENSIONS: [&str; 4] = ["", ".yml", ".yaml", ".layout"];
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Program {
@@ -53,6 +59,12 @@ pub fn get_layout(fname: &str) -> Result<Conf, u8> {
     }
 }
 
+/// returns the `workspaces` map of the layout `fname`, so it can be applied again without
+/// launching any of the layout's programs.
+pub fn get_workspaces(fname: &str) -> Result<HashMap<i32, i32>, u8> {
+    get_layout(fname).map(|layout| layout.workspaces.unwrap_or_default())
+}
+
 fn get_layout_file(file_name: &str) -> Result<String, ()> {
     // let shellexpand::tilde(
     //     &if file_name.ends_with(".layout") || file_name.ends_with(".yml") {
@@ -62,15 +74,15 @@ fn get_layout_file(file_name: &str) -> Result<String, ()> {
     //     },
     // )
     // .to_string();
-    // #[cfg("test")]
-    // {
-    //     if Path::new(file_name).exists() {
-    //         return Ok(Path::new(file_name).to_str().unwrap().to_string());
-    //     }
-    // }
+    // lets tests load the layouts in test-data.
+    #[cfg(test)]
+    {
+        if Path::new(file_name).exists() {
+            return Ok(file_name.to_string());
+        }
+    }
 
-    // TODO: pull path from config file
-    let mut layout_dir = shellexpand::tilde("~/.config/auto-desk/layouts/").to_string();
+    let mut layout_dir = shellexpand::tilde(LAYOUT_DIR).to_string();
 
     if shellexpand::tilde(&file_name)
         .to_string()
@@ -80,13 +92,9 @@ fn get_layout_file(file_name: &str) -> Result<String, ()> {
         return Ok(shellexpand::tilde(file_name).to_string());
     }
 
-    // TODO: pull path from config file
-    layout_dir =
-        shellexpand::tilde(&format!("~/.config/auto-desk/layouts/{}", file_name)).to_string();
+    layout_dir = shellexpand::tilde(&format!("{LAYOUT_DIR}{}", file_name)).to_string();
 
-    let f_types = ["", ".yml", ".yaml", ".layout"];
-
-    for f_type in f_types {
+    for f_type in LAYOUT_EXTENSIONS {
         let p = Path::new(&format!("{}{}", layout_dir, f_type)).to_owned();
         if p.exists() {
             return Ok(p.to_str().unwrap().to_string());
@@ -94,3 +102,113 @@ fn get_layout_file(file_name: &str) -> Result<String, ()> {
     }
     Err(())
 }
+
+/// returns the names of the layouts that `get_layout_file` can find, without their extensions.
+pub fn list_layouts() -> Vec<String> {
+    let layout_dir = shellexpand::tilde(LAYOUT_DIR).to_string();
+    let Ok(entries) = read_dir(layout_dir) else {
+        return Vec::new();
+    };
+
+    let mut layouts: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            LAYOUT_EXTENSIONS
+                .iter()
+                .filter(|ext| !ext.is_empty())
+                .find_map(|ext| name.strip_suffix(ext))
+                .unwrap_or(&name)
+                .to_string()
+        })
+        .collect();
+
+    layouts.sort();
+    layouts.dedup();
+    layouts
+}
+
+/// the name a program's window is matched by, its wm_class or else the name of the executable
+/// that `command` runs, without its path or .desktop.
+pub fn window_name(command: &str, wm_class: Option<&str>) -> String {
+    if let Some(wm_class) = wm_class {
+        return wm_class.to_string();
+    }
+
+    let exec = command.split(' ').next().unwrap_or_default();
+    let exec = exec.rsplit('/').next().unwrap_or_default();
+    exec.trim_end_matches(".desktop").to_string()
+}
+
+/// sets up the desktops of a layout with `set_up`, which returns the exit codes of the programs
+/// it launched. the `asyncro` desktops each get a thread of their own, the rest are set up in
+/// order on one more thread. returns the first error code.
+pub fn launch_layouts<F>(layouts: Vec<DesktopLayout>, set_up: F) -> u8
+where
+    F: Fn(&DesktopLayout) -> Vec<u8> + Send + Sync + 'static,
+{
+    let set_up = Arc::new(set_up);
+    let (async_layouts, sync_layouts): (Vec<_>, Vec<_>) = layouts
+        .into_iter()
+        .partition(|layout| layout.asyncro.unwrap_or(false));
+
+    let mut launchers = Vec::new();
+
+    let tmp_set_up = set_up.clone();
+    launchers.push(thread::spawn(move || {
+        sync_layouts
+            .iter()
+            .flat_map(|layout| tmp_set_up(layout))
+            .collect::<Vec<u8>>()
+    }));
+
+    for layout in async_layouts {
+        let tmp_set_up = set_up.clone();
+        launchers.push(thread::spawn(move || tmp_set_up(&layout)));
+    }
+
+    for launcher in launchers {
+        let err_codes = match launcher.join() {
+            Ok(ecs) => ecs,
+            Err(e) => {
+                error!("got unknown error: {e:?}");
+                vec![2]
+            }
+        };
+        if let Some(ec) = err_codes.into_iter().find(|ec| *ec > 0) {
+            return ec;
+        }
+    }
+
+    0
+}
+
+/// launches `programs` on `desktop` one after another with `launch`, logging the ones that
+/// fail. returns their exit codes.
+pub fn launch_programs(
+    programs: &[Program],
+    desktop: &str,
+    mut launch: impl FnMut(&Program) -> u8,
+) -> Vec<u8> {
+    programs
+        .iter()
+        .map(|program| {
+            let ec = launch(program);
+            if ec > 0 {
+                error!("could not launch {} on desktop {desktop}.", program.name);
+            }
+            ec
+        })
+        .collect()
+}
+
+#[test]
+fn test_window_name() {
+    assert_eq!(
+        window_name("/usr/bin/brave-browser --incognito", None),
+        "brave-browser"
+    );
+    assert_eq!(window_name("firefox.desktop", None), "firefox");
+    assert_eq!(window_name("firefox", Some("Navigator")), "Navigator");
+}
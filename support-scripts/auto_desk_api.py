@@ -16,7 +16,8 @@ By: Calacuda | MIT License | Python Version: 3.9, 3.10
 from libqtile.command.client import InteractiveCommandClient
 from libqtile.log_utils import logger
 from libqtile import hook
-# import os
+import os
+import json
 from socket import socket, AF_UNIX, SOCK_STREAM
 # import asyncio
 
@@ -24,7 +25,24 @@ from socket import socket, AF_UNIX, SOCK_STREAM
 
 QTILE_CLIENT = InteractiveCommandClient()
 NEW_CLIENT_PIDs = set()
-PATH = "/tmp/desktop-automater"
+
+
+def _socket_path():
+    """
+    the server's listen socket, the same one auto-desk picks by default. set AUTO_DESK_SOCKET
+    if `listen_socket` is set in the config.
+    """
+    if os.environ.get("AUTO_DESK_SOCKET"):
+        return os.environ["AUTO_DESK_SOCKET"]
+
+    runtime_dir = os.environ.get("XDG_RUNTIME_DIR")
+    if runtime_dir:
+        return f"{runtime_dir}/auto-desk/auto-desk.sock"
+
+    return f"/tmp/auto-desk-{os.getuid()}/auto-desk.sock"
+
+
+PATH = _socket_path()
 
 
 # @hook.subscribe.client_managed
@@ -85,6 +103,12 @@ def should_clear(group):
     return res == "true"
 
 
+def list_commands():
+    """returns the descriptions of the commands auto-desk knows (see "list-commands" in spec.md)"""
+    res = send_auto_desk("list-commands")
+    return json.loads(res) if res else []
+
+
 def send_auto_desk(message):
     """sends data to auto-desk and returns the response"""
     location = None
@@ -100,8 +124,10 @@ def send_auto_desk(message):
         else:
             s.send(bytes(message, "utf-8"))
             s.shutdown(1)  # tells the server im done sending data and it can reply now.
-            res = s.recv(1024)
-            ec = res[0]
+            res = b""
+            while chunk := s.recv(1024):
+                res += chunk
+            ec = res[0] if res else 8
             if len(res) >= 3:
                 location = res[2:].decode('utf-8')
             if ec: